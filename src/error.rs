@@ -1,4 +1,99 @@
+use std::string::FromUtf8Error;
+
+use thiserror::Error;
+use tokio::sync::mpsc;
+use zeromq::ZmqError;
+
+use crate::jupyter::messages::Message;
+use crate::jupyter::messages::message_type::MessageType;
+use crate::jupyter::messages::multipart::Multipart;
+
 #[derive(Debug)]
 pub enum KernelError {
     MissingFormatDecls { missing: Vec<&'static str> },
 }
+
+/// Errors of receiving, parsing, signing and sending messages.
+///
+/// None of these should bring down the kernel, bad messages are logged and
+/// skipped.
+#[derive(Debug, Error)]
+pub enum MessageError {
+    #[error("could not receive message: {0}")]
+    Recv(#[source] ZmqError),
+
+    #[error("message is missing the {0} frame")]
+    MissingFrame(&'static str),
+
+    #[error("{frame} frame is not valid utf-8")]
+    Utf8 {
+        frame: &'static str,
+        #[source]
+        source: FromUtf8Error,
+    },
+
+    #[error("message has an invalid signature")]
+    InvalidSignature,
+
+    #[error("message was replayed")]
+    Replayed,
+
+    #[error("could not parse {frame} frame: {source}")]
+    Parse {
+        frame: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("unknown message type {}", .0.header.msg_type)]
+    UnknownRequest(Box<Message<()>>),
+
+    #[error("invalid content for {}: {source}", .request.header.msg_type)]
+    InvalidRequest {
+        request: Box<Message<()>>,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("digester is not initialized")]
+    DigesterUninit,
+
+    #[error("could not serialize message: {0}")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("multipart message has no frames")]
+    EmptyMultipart,
+
+    #[error("could not send message: {0}")]
+    Send(#[source] ZmqError),
+
+    #[error("outgoing channel is closed")]
+    ChannelClosed,
+
+    #[error("unknown subshell {}", .0.header.subshell_id.as_deref().unwrap_or_default())]
+    UnknownSubshell(Box<Message<()>>),
+
+    #[error("{0} has no reply")]
+    NoReply(MessageType),
+}
+
+impl MessageError {
+    /// The request that could not be handled, if its header could be parsed.
+    ///
+    /// These requests should get an error reply so that clients don't wait
+    /// forever.
+    pub fn request(&self) -> Option<&Message<()>> {
+        match self {
+            MessageError::UnknownRequest(request) |
+            MessageError::InvalidRequest { request, .. } |
+            MessageError::UnknownSubshell(request) => Some(request),
+            _ => None,
+        }
+    }
+}
+
+impl From<mpsc::error::SendError<Multipart>> for MessageError {
+    fn from(_: mpsc::error::SendError<Multipart>) -> Self {
+        MessageError::ChannelClosed
+    }
+}
@@ -3,25 +3,75 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 
+use super::ShutdownSignal;
+use super::debug::handle_debug_request;
+use super::subshell::Subshells;
 use crate::ControlSocket;
+use crate::error::MessageError;
 use crate::jupyter::Shutdown;
 use crate::jupyter::kernel_info::KernelInfo;
-use crate::jupyter::messages::control::{ControlReply, ControlReplyOk, ControlRequest};
+use crate::jupyter::messages::control::{
+    ControlReply, ControlReplyOk, ControlRequest, CreateSubshellReply, DeleteSubshellRequest,
+    ListSubshellReply,
+};
+use crate::jupyter::messages::multipart::Multipart;
 use crate::jupyter::messages::{Header, Message, Metadata};
+use crate::jupyter::usage::ResourceUsage;
+use crate::nu::debugger::DebugSession;
+
+/// How long to wait for all handlers to shut down or restart before replying.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub async fn handle(
     mut socket: ControlSocket,
-    shutdown_sender: broadcast::Sender<Shutdown>,
+    shutdown_sender: broadcast::Sender<ShutdownSignal>,
     interrupt_signal: Arc<AtomicBool>,
+    debugger: Arc<DebugSession>,
+    subshells: Arc<Subshells>,
 ) {
+    // replies of requests that are answered later, so that they don't block
+    // interrupts
+    let (replies_tx, mut replies) = mpsc::channel::<Multipart>(1);
+
     loop {
-        let message = Message::<ControlRequest>::recv(&mut socket).await.unwrap();
-        match &message.content {
+        let message = tokio::select! {
+            biased;
+            Some(reply) = replies.recv() => {
+                if let Err(err) = reply.send(&mut socket).await {
+                    eprintln!("could not send control reply: {err}");
+                }
+                continue;
+            }
+            v = Message::<ControlRequest>::recv(&mut socket) => v,
+        };
+        let message = match message {
+            Ok(message) => message,
+            Err(err) => {
+                eprintln!("could not recv control message: {err}");
+                if let Some(request) = err.request() &&
+                    let Err(err) = handle_unhandled_request(&mut socket, request, &err).await
+                {
+                    eprintln!("could not reply to {}: {err}", request.header.msg_type);
+                }
+                continue;
+            }
+        };
+        let handled = match &message.content {
             ControlRequest::KernelInfo => handle_kernel_info_request(&mut socket, &message).await,
             ControlRequest::Shutdown(shutdown) => {
-                handle_shutdown_request(&mut socket, &message, *shutdown, &shutdown_sender).await;
+                let handled = handle_shutdown_request(
+                    &mut socket,
+                    &message,
+                    *shutdown,
+                    &shutdown_sender,
+                    &interrupt_signal,
+                )
+                .await;
+                if let Err(err) = handled {
+                    eprintln!("could not handle {}: {err}", message.header.msg_type);
+                }
                 match shutdown.restart {
                     true => continue,
                     false => break,
@@ -30,15 +80,64 @@ pub async fn handle(
             ControlRequest::Interrupt => {
                 handle_interrupt_request(&mut socket, &message, interrupt_signal.deref()).await
             }
-            ControlRequest::Debug => todo!(),
+            ControlRequest::Debug(request) => {
+                handle_debug_request(&mut socket, &message, request, &debugger).await
+            }
+            ControlRequest::CreateSubshell => {
+                handle_create_subshell_request(&message, &subshells, &replies_tx)
+            }
+            ControlRequest::DeleteSubshell(request) => {
+                handle_delete_subshell_request(&mut socket, &message, request, &subshells).await
+            }
+            ControlRequest::ListSubshell => {
+                handle_list_subshell_request(&mut socket, &message, &subshells).await
+            }
+            ControlRequest::Usage => handle_usage_request(&mut socket, &message).await,
+        };
+        if let Err(err) = handled {
+            eprintln!("could not handle {}: {err}", message.header.msg_type);
         }
     }
 }
 
-async fn handle_kernel_info_request(socket: &mut ControlSocket, message: &Message<ControlRequest>) {
+/// Reply with an error to requests that are unknown or have invalid content.
+async fn handle_unhandled_request(
+    socket: &mut ControlSocket,
+    request: &Message<()>,
+    err: &MessageError,
+) -> Result<(), MessageError> {
+    let Some(msg_type) = request.header.msg_type.reply()
+    else {
+        return Ok(());
+    };
+
+    let name = match err {
+        MessageError::UnknownRequest(_) => "UnknownRequest",
+        _ => "InvalidRequest",
+    };
+    let reply = ControlReply::Error {
+        name: name.to_owned(),
+        value: err.to_string(),
+        traceback: vec![],
+    };
+    let reply = Message {
+        zmq_identities: request.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(request.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    reply.into_multipart()?.send(socket).await
+}
+
+async fn handle_kernel_info_request(
+    socket: &mut ControlSocket,
+    message: &Message<ControlRequest>,
+) -> Result<(), MessageError> {
     let kernel_info = KernelInfo::get();
     let reply = ControlReply::Ok(ControlReplyOk::KernelInfo(Box::new(kernel_info)));
-    let msg_type = ControlReply::msg_type(&message.header.msg_type).unwrap();
+    let msg_type = message.header.reply_type()?;
     let reply = Message {
         zmq_identities: message.zmq_identities.clone(),
         header: Header::new(msg_type),
@@ -47,22 +146,39 @@ async fn handle_kernel_info_request(socket: &mut ControlSocket, message: &Messag
         content: reply,
         buffers: vec![],
     };
-    reply.into_multipart().unwrap().send(socket).await.unwrap();
+    reply.into_multipart()?.send(socket).await
 }
 
 async fn handle_shutdown_request(
     socket: &mut ControlSocket,
     message: &Message<ControlRequest>,
     shutdown: Shutdown,
-    sender: &broadcast::Sender<Shutdown>,
-) {
+    sender: &broadcast::Sender<ShutdownSignal>,
+    interrupt_signal: &AtomicBool,
+) -> Result<(), MessageError> {
+    // running executions would keep the shell from shutting down
+    interrupt_signal.store(true, Ordering::Relaxed);
+
     // according to docs, we first shut our kernel and then reply to the client
-    sender.send(shutdown).unwrap();
+    let tasks = sender.receiver_count();
+    let (signal, mut done) = ShutdownSignal::new(shutdown.restart);
+    if sender.send(signal).is_err() {
+        eprintln!("no handler received the shutdown");
+    }
+    let all_done = async {
+        for _ in 0..tasks {
+            done.recv().await;
+        }
+    };
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, all_done)
+        .await
+        .is_err()
+    {
+        eprintln!("not all handlers finished the shutdown in time");
+    }
 
-    // TODO: check if application terminated, maybe with broadcast that ensures that
-    // every       subscriber received something
     let reply = ControlReply::Ok(ControlReplyOk::Shutdown(shutdown));
-    let msg_type = ControlReply::msg_type(&message.header.msg_type).unwrap();
+    let msg_type = message.header.reply_type()?;
     let reply = Message {
         zmq_identities: message.zmq_identities.clone(),
         header: Header::new(msg_type),
@@ -71,14 +187,14 @@ async fn handle_shutdown_request(
         content: reply,
         buffers: vec![],
     };
-    reply.into_multipart().unwrap().send(socket).await.unwrap();
+    reply.into_multipart()?.send(socket).await
 }
 
 async fn handle_interrupt_request(
     socket: &mut ControlSocket,
     message: &Message<ControlRequest>,
     interrupt_signal: &AtomicBool,
-) {
+) -> Result<(), MessageError> {
     interrupt_signal.store(true, Ordering::Relaxed);
 
     while interrupt_signal.load(Ordering::Relaxed) {
@@ -88,7 +204,123 @@ async fn handle_interrupt_request(
     }
 
     let reply = ControlReply::Ok(ControlReplyOk::Interrupt);
-    let msg_type = ControlReply::msg_type(&message.header.msg_type).unwrap();
+    let msg_type = message.header.reply_type()?;
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    reply.into_multipart()?.send(socket).await
+}
+
+/// Create a subshell in the background as it waits for the main shell to be
+/// idle.
+fn handle_create_subshell_request(
+    message: &Message<ControlRequest>,
+    subshells: &Arc<Subshells>,
+    replies: &mpsc::Sender<Multipart>,
+) -> Result<(), MessageError> {
+    let msg_type = message.header.reply_type()?;
+    let zmq_identities = message.zmq_identities.clone();
+    let parent_header = message.header.clone();
+    let subshells = subshells.clone();
+    let replies = replies.clone();
+    tokio::spawn(async move {
+        let reply = match subshells.create().await {
+            Ok(subshell_id) => {
+                ControlReply::Ok(ControlReplyOk::CreateSubshell(CreateSubshellReply {
+                    subshell_id,
+                }))
+            }
+            Err(err) => ControlReply::Error {
+                name: "SubshellError".to_owned(),
+                value: format!("could not create subshell: {err}"),
+                traceback: vec![],
+            },
+        };
+        let reply = Message {
+            zmq_identities,
+            header: Header::new(msg_type),
+            parent_header: Some(parent_header),
+            metadata: Metadata::empty(),
+            content: reply,
+            buffers: vec![],
+        };
+        let sent = match reply.into_multipart() {
+            Ok(multipart) => replies.send(multipart).await.map_err(MessageError::from),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = sent {
+            eprintln!("could not reply to create_subshell_request: {err}");
+        }
+    });
+    Ok(())
+}
+
+async fn handle_delete_subshell_request(
+    socket: &mut ControlSocket,
+    message: &Message<ControlRequest>,
+    request: &DeleteSubshellRequest,
+    subshells: &Subshells,
+) -> Result<(), MessageError> {
+    let reply = match subshells.delete(&request.subshell_id) {
+        true => ControlReply::Ok(ControlReplyOk::DeleteSubshell),
+        false => ControlReply::Error {
+            name: "SubshellError".to_owned(),
+            value: format!("unknown subshell {:?}", request.subshell_id),
+            traceback: vec![],
+        },
+    };
+    let msg_type = message.header.reply_type()?;
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    reply.into_multipart()?.send(socket).await
+}
+
+async fn handle_list_subshell_request(
+    socket: &mut ControlSocket,
+    message: &Message<ControlRequest>,
+    subshells: &Subshells,
+) -> Result<(), MessageError> {
+    let reply = ListSubshellReply {
+        subshell_id: subshells.list(),
+    };
+    let reply = ControlReply::Ok(ControlReplyOk::ListSubshell(reply));
+    let msg_type = message.header.reply_type()?;
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    reply.into_multipart()?.send(socket).await
+}
+
+async fn handle_usage_request(
+    socket: &mut ControlSocket,
+    message: &Message<ControlRequest>,
+) -> Result<(), MessageError> {
+    // measuring may block for a moment to get the cpu usage
+    let reply = match tokio::task::spawn_blocking(ResourceUsage::measure).await {
+        Ok(usage) => ControlReply::Ok(ControlReplyOk::Usage(Box::new(usage))),
+        Err(err) => ControlReply::Error {
+            name: "UsageError".to_owned(),
+            value: format!("could not measure usage: {err}"),
+            traceback: vec![],
+        },
+    };
+    let msg_type = message.header.reply_type()?;
     let reply = Message {
         zmq_identities: message.zmq_identities.clone(),
         header: Header::new(msg_type),
@@ -97,5 +329,5 @@ async fn handle_interrupt_request(
         content: reply,
         buffers: vec![],
     };
-    reply.into_multipart().unwrap().send(socket).await.unwrap();
+    reply.into_multipart()?.send(socket).await
 }
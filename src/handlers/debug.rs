@@ -0,0 +1,190 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use nu_protocol::Config;
+use serde_json::json;
+
+use crate::ControlSocket;
+use crate::error::MessageError;
+use crate::jupyter::messages::control::{ControlReply, ControlReplyOk, ControlRequest};
+use crate::jupyter::messages::debug::{DebugRequest, DebugResponse};
+use crate::jupyter::messages::{Header, Message, Metadata};
+use crate::nu::debugger::{self, DebugSession, HASH_SEED, Resume, THREAD_ID};
+
+pub async fn handle_debug_request(
+    socket: &mut ControlSocket,
+    message: &Message<ControlRequest>,
+    request: &DebugRequest,
+    session: &DebugSession,
+) -> Result<(), MessageError> {
+    let body = respond(session, request);
+    let (success, message_text, body) = match body {
+        Ok(body) => (true, None, body),
+        Err(err) => (false, Some(err), json!({})),
+    };
+    let response = DebugResponse {
+        seq: session.next_seq(),
+        request_seq: request.seq,
+        success,
+        command: request.command.clone(),
+        message: message_text,
+        body,
+    };
+    let reply = ControlReply::Ok(ControlReplyOk::Debug(response));
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(message.header.reply_type()?),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    reply.into_multipart()?.send(socket).await?;
+
+    // the adapter is ready for breakpoints after initializing
+    if request.command == "initialize" && success {
+        session.send_event("initialized", json!({})).await?;
+    }
+    Ok(())
+}
+
+/// Body of the response to a DAP request.
+fn respond(session: &DebugSession, request: &DebugRequest) -> Result<serde_json::Value, String> {
+    let arguments = &request.arguments;
+    match request.command.as_str() {
+        "initialize" => Ok(json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsConditionalBreakpoints": false,
+            "supportsSetVariable": false,
+            "supportsExceptionInfoRequest": false,
+            "exceptionBreakpointFilters": [],
+        })),
+        "attach" => {
+            session.state().attached = true;
+            Ok(json!({}))
+        }
+        "disconnect" => {
+            session.detach();
+            Ok(json!({}))
+        }
+        "configurationDone" | "setExceptionBreakpoints" => Ok(json!({})),
+        "debugInfo" => {
+            let state = session.state();
+            let breakpoints: Vec<_> = state
+                .breakpoints
+                .iter()
+                .map(|(source, lines)| {
+                    json!({
+                        "source": source,
+                        "breakpoints": lines.iter().map(|line| json!({"line": line})).collect::<Vec<_>>(),
+                    })
+                })
+                .collect();
+            let stopped_threads: &[usize] = match state.stopped {
+                Some(_) => &[THREAD_ID],
+                None => &[],
+            };
+            Ok(json!({
+                "isStarted": state.attached,
+                "hashMethod": "Murmur2",
+                "hashSeed": HASH_SEED,
+                "tmpFilePrefix": session.tmp_file_prefix(),
+                "tmpFileSuffix": session.tmp_file_suffix(),
+                "breakpoints": breakpoints,
+                "stoppedThreads": stopped_threads,
+                "richRendering": false,
+                "exceptionPaths": [],
+            }))
+        }
+        "dumpCell" => {
+            let code = arguments["code"].as_str().ok_or("missing code")?;
+            let path = session.dump_cell(code).map_err(|err| err.to_string())?;
+            Ok(json!({"sourcePath": path}))
+        }
+        "setBreakpoints" => {
+            let path = arguments["source"]["path"]
+                .as_str()
+                .ok_or("missing source path")?;
+            let lines: BTreeSet<usize> = arguments["breakpoints"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                .map(|line| line as usize)
+                .collect();
+            let breakpoints: Vec<_> = lines
+                .iter()
+                .map(|line| json!({"verified": true, "line": line, "source": {"path": path}}))
+                .collect();
+            let mut state = session.state();
+            match lines.is_empty() {
+                true => state.breakpoints.remove(path),
+                false => state.breakpoints.insert(path.to_owned(), lines),
+            };
+            Ok(json!({"breakpoints": breakpoints}))
+        }
+        "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "main"}]})),
+        "stackTrace" => {
+            let frames = session.state().stack_frames();
+            Ok(json!({"totalFrames": frames.len(), "stackFrames": frames}))
+        }
+        "scopes" => {
+            let frame_id = arguments["frameId"].as_u64().ok_or("missing frameId")? as usize;
+            let state = session.state();
+            let (locals, globals) = state
+                .stopped
+                .as_ref()
+                .and_then(|stopped| stopped.scopes(frame_id))
+                .ok_or("no such frame")?;
+            Ok(json!({"scopes": [
+                {"name": "Locals", "variablesReference": locals, "expensive": false},
+                {"name": "Globals", "variablesReference": globals, "expensive": false},
+            ]}))
+        }
+        "variables" => {
+            let reference = arguments["variablesReference"]
+                .as_u64()
+                .ok_or("missing variablesReference")? as usize;
+            let mut state = session.state();
+            let stopped = state.stopped.as_mut().ok_or("not stopped")?;
+            Ok(json!({"variables": stopped.variables(reference)}))
+        }
+        "inspectVariables" => {
+            let config = Config::default();
+            let state = session.state();
+            let variables: Vec<_> = state
+                .globals()
+                .iter()
+                .map(|(name, value)| debugger::variable(name, value, &config, 0))
+                .collect();
+            Ok(json!({"variables": variables}))
+        }
+        "source" => {
+            let path = arguments["source"]["path"]
+                .as_str()
+                .ok_or("missing source path")?;
+            let content = match session.state().source(path) {
+                Some(code) => code.to_owned(),
+                None => fs::read_to_string(path).map_err(|err| err.to_string())?,
+            };
+            Ok(json!({"content": content}))
+        }
+        "continue" | "next" | "stepIn" | "stepOut" => {
+            let resume = match request.command.as_str() {
+                "continue" => Resume::Continue,
+                "next" => Resume::Next,
+                "stepIn" => Resume::StepIn,
+                _ => Resume::StepOut,
+            };
+            match session.resume(resume) {
+                true => Ok(json!({"allThreadsContinued": true})),
+                false => Err("not stopped".to_owned()),
+            }
+        }
+        "pause" => {
+            session.pause();
+            Ok(json!({}))
+        }
+        command => Err(format!("unsupported command {command:?}")),
+    }
+}
@@ -1,30 +1,67 @@
+use tokio::sync::mpsc;
+
+/// Tells every task to shut down or restart the kernel.
+///
+/// Each task acknowledges via [`done`](Self::done) once it stopped or
+/// restarted, the shutdown is only replied after all tasks are done.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    pub restart: bool,
+    done: mpsc::UnboundedSender<()>,
+}
+
+impl ShutdownSignal {
+    /// Create a signal and the receiver of its acknowledgements.
+    pub fn new(restart: bool) -> (Self, mpsc::UnboundedReceiver<()>) {
+        let (done, done_rx) = mpsc::unbounded_channel();
+        (ShutdownSignal { restart, done }, done_rx)
+    }
+
+    /// Acknowledge that this task stopped or restarted.
+    pub fn done(&self) {
+        // the control handler may have stopped waiting already
+        _ = self.done.send(());
+    }
+}
+
 pub mod iopub {
     use tokio::sync::{broadcast, mpsc};
 
+    use super::ShutdownSignal;
     use crate::IopubSocket;
-    use crate::jupyter::Shutdown;
     use crate::jupyter::messages::multipart::Multipart;
     use crate::util::Select;
 
     pub async fn handle(
         mut socket: IopubSocket,
-        mut shutdown: broadcast::Receiver<Shutdown>,
+        mut shutdown: broadcast::Receiver<ShutdownSignal>,
         mut iopub_rx: mpsc::Receiver<Multipart>,
     ) {
         loop {
             let next = tokio::select! {
                 biased;
                 v = shutdown.recv() => Select::Left(v),
-                v = iopub_rx.recv() => Select::Right(v.unwrap()),
+                v = iopub_rx.recv() => match v {
+                    Some(multipart) => Select::Right(multipart),
+                    // all senders are gone, nothing can be published anymore
+                    None => break,
+                },
             };
 
             let multipart = match next {
-                Select::Left(Ok(Shutdown { restart: false })) => break,
-                Select::Left(Ok(Shutdown { restart: true })) => continue,
+                Select::Left(Ok(signal)) => {
+                    signal.done();
+                    match signal.restart {
+                        true => continue,
+                        false => break,
+                    }
+                }
                 Select::Left(Err(_)) => break,
                 Select::Right(multipart) => multipart,
             };
-            multipart.send(&mut socket).await.unwrap();
+            if let Err(err) = multipart.send(&mut socket).await {
+                eprintln!("could not publish message: {err}");
+            }
         }
     }
 }
@@ -33,29 +70,46 @@ pub mod heartbeat {
     use tokio::sync::broadcast;
     use zeromq::{SocketRecv, SocketSend};
 
+    use super::ShutdownSignal;
     use crate::HeartbeatSocket;
-    use crate::jupyter::Shutdown;
     use crate::util::Select;
 
-    pub async fn handle(mut socket: HeartbeatSocket, mut shutdown: broadcast::Receiver<Shutdown>) {
+    pub async fn handle(
+        mut socket: HeartbeatSocket,
+        mut shutdown: broadcast::Receiver<ShutdownSignal>,
+    ) {
         loop {
             let next = tokio::select! {
                 biased;
                 v = shutdown.recv() => Select::Left(v),
-                v = socket.recv() => Select::Right(v.unwrap()),
+                v = socket.recv() => Select::Right(v),
             };
 
             let msg = match next {
-                Select::Left(Ok(Shutdown { restart: false })) => break,
-                Select::Left(Ok(Shutdown { restart: true })) => continue,
+                Select::Left(Ok(signal)) => {
+                    signal.done();
+                    match signal.restart {
+                        true => continue,
+                        false => break,
+                    }
+                }
                 Select::Left(Err(_)) => break,
-                Select::Right(msg) => msg,
+                Select::Right(Ok(msg)) => msg,
+                Select::Right(Err(err)) => {
+                    eprintln!("could not recv heartbeat: {err}");
+                    continue;
+                }
             };
-            socket.send(msg).await.unwrap();
+            if let Err(err) = socket.send(msg).await {
+                eprintln!("could not send heartbeat: {err}");
+            }
         }
     }
 }
 
 pub mod control;
+pub mod debug;
 pub mod shell;
+pub mod stdin;
 pub mod stream;
+pub mod subshell;
@@ -1,35 +1,78 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use std::{fs, io, thread};
 
 use mime::Mime;
-use nu_protocol::PipelineData;
+use nu_engine::ClosureEvalOnce;
 use nu_protocol::engine::{EngineState, Stack, StateWorkingSet};
+use nu_protocol::process::ChildPipe;
+use nu_protocol::{
+    ByteStream, IntoValue, ListStream, OutDest, PipelineData, PipelineMetadata, ShellError,
+    Signals, Span, Value, copy_with_signals,
+};
 use parking_lot::Mutex;
 use serde_json::json;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
 use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
 
+use super::ShutdownSignal;
 use super::stream::StreamHandler;
-use crate::ShellSocket;
-use crate::jupyter::Shutdown;
+use super::subshell::{BaseRequest, ShellMessage, Subshells};
+use crate::error::MessageError;
+use crate::history::{History, HistoryEntry};
 use crate::jupyter::kernel_info::KernelInfo;
-use crate::jupyter::messages::iopub::{self, ExecuteResult, IopubBroacast, Status};
+use crate::jupyter::messages::comm::{CommClose, CommInfoReply, CommInfoRequest, CommOpen};
+use crate::jupyter::messages::iopub::{
+    self, DisplayData, ExecuteInput, ExecuteResult, IopubBroacast, Status,
+};
 use crate::jupyter::messages::multipart::Multipart;
 use crate::jupyter::messages::shell::{
-    ExecuteReply, ExecuteRequest, IsCompleteReply, IsCompleteRequest, ShellReply, ShellReplyOk,
-    ShellRequest,
+    CompleteReply, CompleteReplyMetadata, CompleteReplyType, CompleteRequest, ExecuteReply,
+    ExecuteRequest, HistoryAccess, HistoryItem, HistoryReply, HistoryRequest, InspectReply,
+    InspectRequest, IsCompleteRequest, ShellReply, ShellReplyOk, ShellRequest, UserExpression,
 };
 use crate::jupyter::messages::{Header, Message, Metadata};
+use crate::jupyter::usage::is_descendant;
+use crate::nu::commands::comm::{CommMessage, CommRegistry, SharedCommRegistry};
 use crate::nu::commands::external::External;
+use crate::nu::debugger::{self, DebugSession, JupyterDebugger};
+use crate::nu::json::json_to_value;
 use crate::nu::konst::Konst;
 use crate::nu::module::KernelInternalSpans;
+use crate::nu::render::registry::{RendererRegistry, Renderers, SharedRendererRegistry};
 use crate::nu::render::{FormatDeclIds, PipelineRender, StringifiedPipelineRender};
+use crate::nu::settings::{Settings, SharedSettings};
 use crate::nu::{self, ExecuteError, ReportExecuteError};
 use crate::util::Select;
 
+/// How long after the reply to a failed execution received requests are
+/// aborted.
+///
+/// Frontends send all requests of "Run All" at once, so every request that
+/// should be aborted is already queued when the error occurs.
+const ABORT_QUEUE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Minimum time between two displays of a streamed list.
+const STREAM_UPDATE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Items of a streamed list shown while it runs, only the latest ones are
+/// displayed to keep updates cheap for long streams.
+const STREAM_PREVIEW_ITEMS: usize = 10;
+
+/// How often a running external is checked for interrupts.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 // TODO: get rid of this static by passing this into the display command
 pub static RENDER_FILTER: Mutex<Option<Mime>> = Mutex::new(Option::None);
 
 pub struct HandlerContext {
-    pub socket: ShellSocket,
+    /// Replies are sent by the router as all shells share the socket.
+    pub shell: mpsc::Sender<Multipart>,
     pub iopub: mpsc::Sender<Multipart>,
     pub stdout_handler: StreamHandler,
     pub stderr_handler: StreamHandler,
@@ -38,63 +81,303 @@ pub struct HandlerContext {
     pub konst: Konst,
     pub spans: KernelInternalSpans,
     pub stack: Stack,
-    pub cell: Cell,
+    /// Shared with the subshells, so that their history lines don't collide.
+    pub cell: Arc<Mutex<Cell>>,
+    pub comms: SharedCommRegistry,
+    pub renderers: SharedRendererRegistry,
+    pub settings: SharedSettings,
+    /// Display ids of `nuju print`, forgotten on restarts.
+    pub display_ids: Arc<Mutex<HashSet<String>>>,
+    pub history: Arc<Mutex<History>>,
+    pub debugger: Arc<DebugSession>,
+    /// `None` for the main shell.
+    pub subshell_id: Option<String>,
+    pub subshells: Arc<Subshells>,
+}
+
+impl HandlerContext {
+    /// Current state of this shell for new subshells to start from.
+    pub fn base(&self) -> SubshellBase {
+        SubshellBase {
+            shell: self.shell.clone(),
+            iopub: self.iopub.clone(),
+            engine_state: self.engine_state.clone(),
+            format_decl_ids: self.format_decl_ids,
+            konst: self.konst,
+            spans: self.spans.clone(),
+            stack: self.stack.clone(),
+            cell: self.cell.clone(),
+            comms: self.comms.clone(),
+            renderers: self.renderers.clone(),
+            // taken together with the engine state their closures belong to
+            inherited_renderers: self.renderers.lock().shell(self.subshell_id.as_deref()),
+            settings: self.settings.clone(),
+            display_ids: self.display_ids.clone(),
+            history: self.history.clone(),
+            debugger: self.debugger.clone(),
+        }
+    }
+}
+
+impl HandlerContext {
+    /// Rebuild the per-session state for a restart of the kernel.
+    fn restart(&mut self, initial_engine_state: &EngineState) -> io::Result<()> {
+        // fresh streams, so that no output of the last session is left
+        let (stdout_handler, stdout_file) =
+            StreamHandler::start(iopub::StreamName::Stdout, self.iopub.clone())?;
+        let (stderr_handler, stderr_file) =
+            StreamHandler::start(iopub::StreamName::Stderr, self.iopub.clone())?;
+        self.stdout_handler = stdout_handler;
+        self.stderr_handler = stderr_handler;
+
+        for plugin in self.engine_state.plugins() {
+            if let Err(err) = plugin.reset() {
+                eprintln!("could not reset plugin {}: {err}", plugin.identity().name());
+            }
+        }
+        self.engine_state = initial_engine_state.clone();
+        // the control handler interrupts running executions before restarting
+        self.engine_state.reset_signals();
+        self.stack = Stack::new()
+            .stdout_file(stdout_file)
+            .stderr_file(stderr_file);
+
+        *self.cell.lock() = Cell::new();
+        *self.comms.lock() = CommRegistry::default();
+        *self.renderers.lock() = RendererRegistry::default();
+        *self.settings.lock() = Settings::default();
+        self.display_ids.lock().clear();
+        *self.history.lock() = History::load();
+        self.debugger.detach();
+        External::reset();
+        RENDER_FILTER.lock().take();
+        Ok(())
+    }
+}
+
+/// State a subshell starts from, see [`HandlerContext::base`].
+pub struct SubshellBase {
+    shell: mpsc::Sender<Multipart>,
+    iopub: mpsc::Sender<Multipart>,
+    engine_state: EngineState,
+    format_decl_ids: FormatDeclIds,
+    konst: Konst,
+    spans: KernelInternalSpans,
+    stack: Stack,
+    cell: Arc<Mutex<Cell>>,
+    comms: SharedCommRegistry,
+    renderers: SharedRendererRegistry,
+    inherited_renderers: Renderers,
+    settings: SharedSettings,
+    display_ids: Arc<Mutex<HashSet<String>>>,
+    history: Arc<Mutex<History>>,
+    debugger: Arc<DebugSession>,
+}
+
+impl SubshellBase {
+    /// Start the context of a subshell with its own output streams.
+    pub fn start(self, id: String, subshells: Arc<Subshells>) -> io::Result<HandlerContext> {
+        let (stdout_handler, stdout_file) =
+            StreamHandler::start(iopub::StreamName::Stdout, self.iopub.clone())?;
+        let (stderr_handler, stderr_file) =
+            StreamHandler::start(iopub::StreamName::Stderr, self.iopub.clone())?;
+        self.renderers
+            .lock()
+            .insert_subshell(id.clone(), self.inherited_renderers);
+        Ok(HandlerContext {
+            shell: self.shell,
+            iopub: self.iopub,
+            stdout_handler,
+            stderr_handler,
+            engine_state: self.engine_state,
+            format_decl_ids: self.format_decl_ids,
+            konst: self.konst,
+            spans: self.spans,
+            stack: self.stack.stdout_file(stdout_file).stderr_file(stderr_file),
+            cell: self.cell,
+            comms: self.comms,
+            renderers: self.renderers,
+            settings: self.settings,
+            display_ids: self.display_ids,
+            history: self.history,
+            debugger: self.debugger,
+            subshell_id: Some(id),
+            subshells,
+        })
+    }
 }
 
-pub async fn handle(mut ctx: HandlerContext, mut shutdown: broadcast::Receiver<Shutdown>) {
+/// Execution loop of a shell.
+///
+/// Only the main shell gets `base_requests` to provide the state for new
+/// subshells.
+pub async fn handle(
+    mut ctx: HandlerContext,
+    mut shutdown: broadcast::Receiver<ShutdownSignal>,
+    mut requests: mpsc::UnboundedReceiver<ShellMessage>,
+    mut base_requests: Option<mpsc::UnboundedReceiver<BaseRequest>>,
+) {
     let initial_engine_state = ctx.engine_state.clone();
-    let initial_stack = ctx.stack.clone();
+    // after a failed execution with `stop_on_error` the requests received until
+    // this deadline get aborted
+    let mut abort_deadline: Option<Instant> = None;
 
     loop {
         let next = tokio::select! {
             biased;
             v = shutdown.recv() => Select::Left(v),
-            v = Message::<ShellRequest>::recv(&mut ctx.socket) => Select::Right(v),
+            Some(base_request) = next_base_request(&mut base_requests) => {
+                // the subshell may already be given up on
+                _ = base_request.send(ctx.base());
+                continue;
+            }
+            v = requests.recv() => match v {
+                Some(message) => Select::Right(message),
+                // the subshell got deleted
+                None => break,
+            },
         };
 
         let message = match next {
-            Select::Left(Ok(Shutdown { restart: false })) => break,
-            Select::Left(Ok(Shutdown { restart: true })) => {
-                ctx.engine_state = initial_engine_state.clone();
-                ctx.stack = initial_stack.clone();
-                // TODO: check if cell counter should get a reset too
+            Select::Left(Ok(signal)) if signal.restart && ctx.subshell_id.is_none() => {
+                if let Err(err) = ctx.restart(&initial_engine_state) {
+                    eprintln!("could not restart shell: {err}");
+                }
+                abort_deadline = None;
+                signal.done();
                 continue;
             }
+            // subshells don't survive restarts
+            Select::Left(Ok(signal)) => {
+                signal.done();
+                break;
+            }
             Select::Left(Err(_)) => break,
             Select::Right(Ok(msg)) => msg,
-            Select::Right(Err(_)) => {
-                eprintln!("could not recv message");
+            Select::Right(Err(err)) => {
+                eprintln!("could not recv message: {err}");
+                if let Some(request) = err.request() {
+                    send_status(&mut ctx, &request.header, Status::Busy).await;
+                    if let Err(err) = handle_unhandled_request(&mut ctx, request, &err).await {
+                        eprintln!("could not reply to {}: {err}", request.header.msg_type);
+                    }
+                    send_status(&mut ctx, &request.header, Status::Idle).await;
+                }
                 continue;
             }
         };
 
-        send_status(&mut ctx, &message, Status::Busy).await;
+        send_status(&mut ctx, &message.header, Status::Busy).await;
 
-        match &message.content {
+        let aborted = abort_deadline.is_some_and(|deadline| Instant::now() < deadline);
+        let handled = match &message.content {
+            _ if aborted => handle_aborted_request(&mut ctx, &message).await,
             ShellRequest::KernelInfo => handle_kernel_info_request(&mut ctx, &message).await,
-            ShellRequest::Execute(request) => {
-                // take the context out temporarily to allow execution on another thread
-                ctx = handle_execute_request(ctx, &message, request).await;
-            }
+            ShellRequest::Execute(request) => handle_execute_request(&mut ctx, &message, request)
+                .await
+                .map(|failed| {
+                    // measured from the reply, so that later requests are not aborted
+                    let aborting = failed && request.stop_on_error && !request.silent;
+                    abort_deadline = aborting.then(|| Instant::now() + ABORT_QUEUE_TIMEOUT);
+                }),
             ShellRequest::IsComplete(request) => {
                 handle_is_complete_request(&mut ctx, &message, request).await
             }
+            ShellRequest::Complete(request) => {
+                handle_complete_request(&mut ctx, &message, request).await
+            }
+            ShellRequest::Inspect(request) => {
+                handle_inspect_request(&mut ctx, &message, request).await
+            }
+            ShellRequest::CommInfo(request) => {
+                handle_comm_info_request(&mut ctx, &message, request).await
+            }
+            ShellRequest::CommOpen(comm_open) => {
+                handle_comm_open(&mut ctx, &message, comm_open).await
+            }
+            ShellRequest::CommMsg(comm_msg) => {
+                run_comm_handler(&mut ctx, &message, &comm_msg.comm_id, &comm_msg.data).await;
+                Ok(())
+            }
+            ShellRequest::CommClose(comm_close) => {
+                ctx.comms.lock().close(&comm_close.comm_id);
+                Ok(())
+            }
+            ShellRequest::History(request) => {
+                handle_history_request(&mut ctx, &message, request).await
+            }
+        };
+        if let Err(err) = handled {
+            eprintln!("could not handle {}: {err}", message.header.msg_type);
         }
 
-        send_status(&mut ctx, &message, Status::Idle).await;
+        send_status(&mut ctx, &message.header, Status::Idle).await;
+    }
+
+    // closures of a deleted subshell can't run anywhere else
+    if let Some(subshell_id) = &ctx.subshell_id {
+        ctx.comms.lock().remove_subshell(subshell_id);
+        ctx.renderers.lock().remove_subshell(subshell_id);
     }
 }
 
-async fn send_status(ctx: &mut HandlerContext, message: &Message<ShellRequest>, status: Status) {
-    ctx.iopub
-        .send(
-            status
-                .into_message(message.header.clone())
-                .into_multipart()
-                .unwrap(),
-        )
-        .await
-        .unwrap();
+/// Receive the next request for the state of the main shell, never resolves
+/// for subshells.
+async fn next_base_request(
+    base_requests: &mut Option<mpsc::UnboundedReceiver<BaseRequest>>,
+) -> Option<BaseRequest> {
+    match base_requests {
+        Some(base_requests) => base_requests.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Publish the execution state, failures are only logged as the kernel keeps
+/// working without status updates.
+async fn send_status(ctx: &mut HandlerContext, parent_header: &Header, status: Status) {
+    let sent = match status.into_message(parent_header.clone()).into_multipart() {
+        Ok(multipart) => ctx.iopub.send(multipart).await.map_err(MessageError::from),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = sent {
+        eprintln!("could not send status: {err}");
+    }
+}
+
+/// Reply with an error to requests that are unknown or have invalid content.
+///
+/// Only requests get a reply, other messages like comm messages are skipped.
+async fn handle_unhandled_request(
+    ctx: &mut HandlerContext,
+    request: &Message<()>,
+    err: &MessageError,
+) -> Result<(), MessageError> {
+    let Some(msg_type) = request.header.msg_type.reply()
+    else {
+        return Ok(());
+    };
+
+    let name = match err {
+        MessageError::UnknownRequest(_) => "UnknownRequest",
+        MessageError::UnknownSubshell(_) => "UnknownSubshell",
+        _ => "InvalidRequest",
+    };
+    let reply = ShellReply::Error {
+        name: name.to_owned(),
+        value: err.to_string(),
+        traceback: vec![],
+        execution_count: None,
+    };
+    let reply = Message {
+        zmq_identities: request.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(request.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
 }
 
 /// Representation of cell execution in Jupyter.
@@ -145,10 +428,13 @@ impl Cell {
     }
 }
 
-async fn handle_kernel_info_request(ctx: &mut HandlerContext, message: &Message<ShellRequest>) {
+async fn handle_kernel_info_request(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+) -> Result<(), MessageError> {
     let kernel_info = KernelInfo::get();
     let reply = ShellReply::Ok(ShellReplyOk::KernelInfo(kernel_info));
-    let msg_type = ShellReply::msg_type(&message.header.msg_type).unwrap();
+    let msg_type = message.header.reply_type()?;
     let reply = Message {
         zmq_identities: message.zmq_identities.clone(),
         header: Header::new(msg_type),
@@ -157,19 +443,15 @@ async fn handle_kernel_info_request(ctx: &mut HandlerContext, message: &Message<
         content: reply,
         buffers: vec![],
     };
-    reply
-        .into_multipart()
-        .unwrap()
-        .send(&mut ctx.socket)
-        .await
-        .unwrap();
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
 }
 
 async fn handle_execute_request(
-    mut ctx: HandlerContext,
+    ctx: &mut HandlerContext,
     message: &Message<ShellRequest>,
     request: &ExecuteRequest,
-) -> HandlerContext {
+) -> Result<bool, MessageError> {
     let ExecuteRequest {
         code,
         silent,
@@ -178,62 +460,345 @@ async fn handle_execute_request(
         allow_stdin,
         stop_on_error,
     } = request;
-    let msg_type = ShellReply::msg_type(&message.header.msg_type).unwrap();
-    External::apply(&mut ctx.engine_state).unwrap();
+    let silent = *silent;
+    // failing to enable externals is reported like a failed execution
+    let applied = External::apply(&mut ctx.engine_state);
 
-    let cell_name = ctx.cell.next_name();
-    ctx.konst
-        .update(&mut ctx.stack, cell_name.clone(), message.clone());
-    ctx.stdout_handler
-        .update_reply(message.zmq_identities.clone(), message.header.clone());
-    ctx.stderr_handler
-        .update_reply(message.zmq_identities.clone(), message.header.clone());
-
-    // TODO: place coll in cell, then just pass the cell
-    let code = code.to_owned();
-    let (executed, mut ctx) = tokio::task::spawn_blocking(move || {
-        (
-            nu::execute(&code, &mut ctx.engine_state, &mut ctx.stack, &cell_name),
-            ctx,
-        )
-    })
-    .await
-    .unwrap();
-    match executed {
-        Ok(data) => handle_execute_results(&mut ctx, message, msg_type, data).await,
-        Err(error) => handle_execute_error(&mut ctx, message, msg_type, error).await,
+    let cell_name = ctx.cell.lock().next_name();
+    ctx.konst.update(
+        &mut ctx.stack,
+        cell_name.clone(),
+        message.clone(),
+        request,
+        ctx.subshell_id.clone(),
+    );
+    ctx.stdout_handler.update_reply(
+        message.zmq_identities.clone(),
+        message.header.clone(),
+        silent,
+    );
+    ctx.stderr_handler.update_reply(
+        message.zmq_identities.clone(),
+        message.header.clone(),
+        silent,
+    );
+
+    if !silent {
+        let broadcast = IopubBroacast::ExecuteInput(ExecuteInput {
+            code: code.to_owned(),
+            execution_count: ctx.cell.lock().execution_count(),
+        });
+        let broadcast = Message {
+            zmq_identities: message.zmq_identities.clone(),
+            header: Header::new(broadcast.msg_type()),
+            parent_header: Some(message.header.clone()),
+            metadata: Metadata::empty(),
+            content: broadcast,
+            buffers: vec![],
+        };
+        let sent = match broadcast.into_multipart() {
+            Ok(multipart) => ctx.iopub.send(multipart).await.map_err(MessageError::from),
+            Err(err) => Err(err),
+        };
+        sent?;
+    }
+
+    // the debugger is shared by all engine states, so only the main shell can be
+    // debugged
+    let debuggable = ctx.subshell_id.is_none();
+    if debuggable && ctx.debugger.is_attached() {
+        ctx.debugger.register_cell(&cell_name, code);
+        ctx.debugger
+            .set_globals(debugger::stack_variables(&ctx.engine_state, &ctx.stack));
+        let debugger = JupyterDebugger::new(ctx.debugger.clone());
+        if let Err(err) = ctx.engine_state.activate_debugger(Box::new(debugger)) {
+            eprintln!("could not activate debugger: {err}");
+        }
+    }
+
+    let executed = applied.map_err(ExecuteError::from).and_then(|()| {
+        run_blocking(|| {
+            // rendering may still fail, e.g. on errors inside of lazy streams,
+            // it also runs here as lazy streams may hit breakpoints
+            nu::execute(code, &mut ctx.engine_state, &mut ctx.stack, &cell_name)
+                .and_then(|data| render_result(ctx, message, data, silent))
+        })
+    });
+
+    if ctx.engine_state.is_debugging() {
+        if let Err(err) = ctx.engine_state.deactivate_debugger() {
+            eprintln!("could not deactivate debugger: {err}");
+        }
+        ctx.debugger.finish_execution();
+        ctx.debugger
+            .set_globals(debugger::stack_variables(&ctx.engine_state, &ctx.stack));
+    }
+
+    let failed = executed.is_err();
+    let sent = match executed {
+        Ok(render) => handle_execute_results(ctx, message, render, request).await,
+        Err(error) => handle_execute_error(ctx, message, error, request).await,
     };
 
     // reset interrupt signal after every execution, this also notifies the control
     // handler
     ctx.engine_state.reset_signals();
 
-    ctx
+    sent.map(|_| failed)
 }
 
-async fn handle_execute_error(
+/// Run nu code of a request while the other tasks move on to other threads.
+///
+/// Panics inside of nu are turned into errors, so that the request gets an
+/// error reply instead of taking down the kernel.
+fn run_blocking<T, E: From<ShellError>>(run: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    tokio::task::block_in_place(|| {
+        panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|payload| {
+            let msg = match payload.downcast::<String>() {
+                Ok(msg) => *msg,
+                Err(payload) => payload
+                    .downcast_ref::<&str>()
+                    .map_or_else(String::new, |msg| msg.to_string()),
+            };
+            Err(ShellError::NushellFailed {
+                msg: format!("nushell panicked: {msg}"),
+            }
+            .into())
+        })
+    })
+}
+
+/// Rendered result of an execution.
+struct RenderedResult {
+    render: StringifiedPipelineRender,
+    /// Already shown as the last update of a streamed list, so it's not
+    /// published as the execution result again.
+    displayed: bool,
+}
+
+/// Render the result of an execution.
+///
+/// Silent executions are not rendered but still drained for their side
+/// effects.
+/// Streams are shown while they run, list streams as a display updated until
+/// it shows the full list and the stdout of externals as stream output.
+fn render_result(
     ctx: &mut HandlerContext,
     message: &Message<ShellRequest>,
-    msg_type: &str,
-    error: ExecuteError,
-) {
-    let mut working_set = StateWorkingSet::new(&ctx.engine_state);
-    let (name, value) = {
-        // keeping the report makes the following part not Send
-        let report = ReportExecuteError::new(error, &mut working_set);
-        let name = report.code().to_string();
-        let value = report.fmt();
-        (name, value)
+    pipeline_data: PipelineData,
+    silent: bool,
+) -> Result<Option<RenderedResult>, ExecuteError> {
+    if silent {
+        pipeline_data.drain()?;
+        return Ok(None);
+    }
+
+    let (pipeline_data, transient) = match pipeline_data {
+        PipelineData::ListStream(stream, metadata) => stream_list(ctx, message, stream, metadata)?,
+        PipelineData::ByteStream(stream, _) if stream.source().is_external() => {
+            stream_external(ctx, stream)?;
+            return Ok(None);
+        }
+        pipeline_data => (pipeline_data, None),
     };
-    // TODO: for traceback use error source
-    let traceback = vec![];
+    if pipeline_data.is_nothing() {
+        return Ok(None);
+    }
 
-    // we send display data to have control over the rendering of the output
-    let broadcast = IopubBroacast::DisplayData(iopub::DisplayData {
-        data: HashMap::from([(mime::TEXT_PLAIN.to_string(), value.clone())]),
-        metadata: HashMap::new(),
-        transient: HashMap::new(),
-    });
+    let renderers = ctx.renderers.lock().shell(ctx.subshell_id.as_deref());
+    let mut render_filter = RENDER_FILTER.lock();
+    let render = PipelineRender::render(
+        pipeline_data,
+        &ctx.engine_state,
+        &mut ctx.stack,
+        &ctx.spans,
+        ctx.format_decl_ids,
+        &renderers,
+        &ctx.settings,
+        render_filter.take(),
+    )
+    .map_err(ShellError::from)?;
+    let render = StringifiedPipelineRender::from(render);
+
+    let displayed = transient.is_some();
+    if let Some(transient) = transient {
+        let display_data = DisplayData {
+            data: render.data.clone(),
+            metadata: render.metadata.clone(),
+            transient,
+        };
+        publish_progress(ctx, message, IopubBroacast::UpdateDisplayData(display_data));
+    }
+    Ok(Some(RenderedResult { render, displayed }))
+}
+
+/// Collect a list stream while displaying the latest items collected so far.
+///
+/// Returns the transient data of the display if the stream ran long enough to
+/// be displayed, the display is then updated to show the full list.
+fn stream_list(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    stream: ListStream,
+    metadata: Option<PipelineMetadata>,
+) -> Result<(PipelineData, Option<HashMap<String, String>>), ExecuteError> {
+    let span = stream.span();
+    let transient = HashMap::from([("display_id".to_owned(), Uuid::new_v4().to_string())]);
+    let mut displayed = false;
+    let mut last_update = Instant::now();
+    let mut items = Vec::new();
+    let renderers = ctx.renderers.lock().shell(ctx.subshell_id.as_deref());
+    for item in stream {
+        items.push(item);
+        if last_update.elapsed() < STREAM_UPDATE_INTERVAL {
+            continue;
+        }
+
+        let preview = items[items.len().saturating_sub(STREAM_PREVIEW_ITEMS)..].to_vec();
+        let partial = PipelineData::Value(Value::list(preview, span), metadata.clone());
+        let render = PipelineRender::render(
+            partial,
+            &ctx.engine_state,
+            &mut ctx.stack,
+            &ctx.spans,
+            ctx.format_decl_ids,
+            &renderers,
+            &ctx.settings,
+            RENDER_FILTER.lock().clone(),
+        )
+        .map_err(ShellError::from)?;
+        let render = StringifiedPipelineRender::from(render);
+        let display_data = DisplayData {
+            data: render.data,
+            metadata: render.metadata,
+            transient: transient.clone(),
+        };
+        let broadcast = match displayed {
+            false => IopubBroacast::DisplayData(display_data),
+            true => IopubBroacast::UpdateDisplayData(display_data),
+        };
+        publish_progress(ctx, message, broadcast);
+        displayed = true;
+        last_update = Instant::now();
+    }
+
+    // list streams just end when interrupted
+    ctx.engine_state.signals().check(&span)?;
+    let list = PipelineData::Value(Value::list(items, span), metadata);
+    Ok((list, displayed.then_some(transient)))
+}
+
+/// Forward the stdout of an external command into the stdout of the stack
+/// while it runs, its stderr is already written there directly.
+///
+/// On interrupts the external is killed.
+fn stream_external(ctx: &mut HandlerContext, stream: ByteStream) -> Result<(), ExecuteError> {
+    let span = stream.span();
+    let Ok(mut child) = stream.into_child()
+    else {
+        unreachable!("stream is checked to be external");
+    };
+
+    // without pipe redirections the stack falls back to its stdout
+    let stdout_file = match ctx.stack.push_redirection(None, None).stdout() {
+        OutDest::File(file) => Some(file.clone()),
+        _ => None,
+    };
+    if let (Some(stdout), Some(stdout_file)) = (child.stdout.take(), stdout_file) {
+        // only the external and its children write into this pipe
+        let pipe = pipe_id(&stdout);
+        // reading blocks until the external writes, so copy on another thread
+        // to stay interruptible
+        let cancel = Arc::new(AtomicBool::new(false));
+        let copy_signals = Signals::new(cancel.clone());
+        let copying = thread::spawn(move || {
+            copy_with_signals(stdout, stdout_file.as_ref(), span, &copy_signals)
+        });
+        while !copying.is_finished() {
+            if let Err(err) = ctx.engine_state.signals().check(&span) {
+                // stops forwarding after the next chunk of output
+                cancel.store(true, Ordering::Relaxed);
+                // killing closes the pipe, so copying finishes and the child can be reaped,
+                // otherwise both are left behind rather than blocking the shell
+                if pipe.as_deref().is_some_and(kill_writers) {
+                    _ = copying.join();
+                    _ = child.wait();
+                }
+                return Err(err.into());
+            }
+            thread::sleep(INTERRUPT_POLL_INTERVAL);
+        }
+        copying.join().expect("copying stdout does not panic")?;
+    }
+
+    // piped externals ignore their exit code, the last one in the terminal does not
+    child.ignore_error(false);
+    child.wait()?;
+    Ok(())
+}
+
+/// Identity of the pipe an external writes its stdout into.
+///
+/// Only available on Linux, elsewhere interrupted externals are left running.
+#[cfg(target_os = "linux")]
+fn pipe_id(pipe: &ChildPipe) -> Option<PathBuf> {
+    use std::os::fd::AsRawFd;
+
+    match pipe {
+        ChildPipe::Pipe(reader) => {
+            fs::read_link(format!("/proc/self/fd/{}", reader.as_raw_fd())).ok()
+        }
+        ChildPipe::Tee(_) => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pipe_id(_: &ChildPipe) -> Option<PathBuf> {
+    None
+}
+
+/// Kill the processes of the kernel writing their stdout into `pipe` together
+/// with their own children.
+///
+/// The child of an external stream doesn't expose its pid, but only the
+/// external of this execution writes into its pipe, so externals of other
+/// shells, plugins and background jobs are spared.
+/// Returns whether any process was killed.
+fn kill_writers(pipe: &Path) -> bool {
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+    let kernel = Pid::from_u32(std::process::id());
+    let writes_pipe =
+        |pid: Pid| fs::read_link(format!("/proc/{pid}/fd/1")).is_ok_and(|link| link == pipe);
+    let writers: Vec<Pid> = system
+        .processes()
+        .values()
+        .filter(|process| process.thread_kind().is_none())
+        .map(|process| process.pid())
+        .filter(|&pid| is_descendant(&system, pid, kernel) && writes_pipe(pid))
+        .collect();
+
+    let mut killed = false;
+    for process in system.processes().values() {
+        let pid = process.pid();
+        if writers
+            .iter()
+            .any(|&writer| pid == writer || is_descendant(&system, pid, writer))
+        {
+            killed |= process.kill();
+        }
+    }
+    killed
+}
+
+/// Publish a broadcast for an execute request from a blocking thread.
+///
+/// Progress is only informative, so failing to publish it is only logged.
+fn publish_progress(
+    ctx: &HandlerContext,
+    message: &Message<ShellRequest>,
+    broadcast: IopubBroacast,
+) {
     let broadcast = Message {
         zmq_identities: message.zmq_identities.clone(),
         header: Header::new(broadcast.msg_type()),
@@ -242,24 +807,33 @@ async fn handle_execute_error(
         content: broadcast,
         buffers: vec![],
     };
-    ctx.iopub
-        .send(broadcast.into_multipart().unwrap())
-        .await
-        .unwrap();
+    let sent = match broadcast.into_multipart() {
+        Ok(multipart) => ctx
+            .iopub
+            .blocking_send(multipart)
+            .map_err(MessageError::from),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = sent {
+        eprintln!("could not publish progress: {err}");
+    }
+}
 
-    // Special case: execute_reply should always contain execution_count
-    // https://jupyter-client.readthedocs.io/en/stable/messaging.html#request-reply
+/// Reply to a queued request that was aborted due to a previous error.
+async fn handle_aborted_request(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+) -> Result<(), MessageError> {
+    // comm messages have no reply
+    let Some(msg_type) = message.header.msg_type.reply()
+    else {
+        return Ok(());
+    };
     let execution_count = match message.content {
-        ShellRequest::Execute(_) => Some(ctx.cell.execution_count()),
+        ShellRequest::Execute(_) => Some(ctx.cell.lock().execution_count()),
         _ => None,
     };
-
-    let reply = ShellReply::Error {
-        name,
-        value,
-        traceback,
-        execution_count,
-    };
+    let reply = ShellReply::Aborted { execution_count };
     let reply = Message {
         zmq_identities: message.zmq_identities.clone(),
         header: Header::new(msg_type),
@@ -268,44 +842,32 @@ async fn handle_execute_error(
         content: reply,
         buffers: vec![],
     };
-    reply
-        .into_multipart()
-        .unwrap()
-        .send(&mut ctx.socket)
-        .await
-        .unwrap();
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
 }
 
-async fn handle_execute_results(
+async fn handle_execute_error(
     ctx: &mut HandlerContext,
     message: &Message<ShellRequest>,
-    msg_type: &str,
-    pipeline_data: PipelineData,
-) {
-    let execution_count = ctx.cell.success();
-
-    if !pipeline_data.is_nothing() {
-        let render: StringifiedPipelineRender = {
-            // render filter needs to be dropped until next async yield
-            let mut render_filter = RENDER_FILTER.lock();
-            PipelineRender::render(
-                pipeline_data,
-                &ctx.engine_state,
-                &mut ctx.stack,
-                &ctx.spans,
-                ctx.format_decl_ids,
-                render_filter.take(),
-            )
-            .unwrap() // TODO: replace this with some actual handling
-            .into()
-        };
+    error: ExecuteError,
+    request: &ExecuteRequest,
+) -> Result<(), MessageError> {
+    let mut working_set = StateWorkingSet::new(&ctx.engine_state);
+    let (name, value, traceback) = {
+        // keeping the report makes the following part not Send
+        let report = ReportExecuteError::new(error, &mut working_set);
+        let name = report.code().to_string();
+        let value = report.to_string();
+        let traceback = report.traceback();
+        (name, value, traceback)
+    };
 
-        let execute_result = ExecuteResult {
-            execution_count,
-            data: render.data,
-            metadata: render.metadata,
-        };
-        let broadcast = IopubBroacast::from(execute_result);
+    if !request.silent {
+        let broadcast = IopubBroacast::Error(iopub::Error {
+            name: name.clone(),
+            value: value.clone(),
+            traceback: traceback.clone(),
+        });
         let broadcast = Message {
             zmq_identities: message.zmq_identities.clone(),
             header: Header::new(broadcast.msg_type()),
@@ -314,41 +876,482 @@ async fn handle_execute_results(
             content: broadcast,
             buffers: vec![],
         };
-        ctx.iopub
-            .send(broadcast.into_multipart().unwrap())
-            .await
-            .unwrap();
+        ctx.iopub.send(broadcast.into_multipart()?).await?;
     }
 
+    // failed executions don't count as a new cell, they aren't recorded in the
+    // history as their line belongs to the next successful execution
+
+    // Special case: execute_reply should always contain execution_count
+    // https://jupyter-client.readthedocs.io/en/stable/messaging.html#request-reply
+    let execution_count = Some(ctx.cell.lock().execution_count());
+
+    let reply = ShellReply::Error {
+        name,
+        value,
+        traceback,
+        execution_count,
+    };
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(message.header.reply_type()?),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
+}
+
+async fn handle_execute_results(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    rendered: Option<RenderedResult>,
+    request: &ExecuteRequest,
+) -> Result<(), MessageError> {
+    // only stored executions count as a new cell
+    let execution_count = match stores_history(request) {
+        true => ctx.cell.lock().success(),
+        false => ctx.cell.lock().execution_count(),
+    };
+
+    let mut output = None;
+    if let Some(RenderedResult { render, displayed }) = rendered {
+        output = render
+            .data
+            .get(mime::TEXT_PLAIN.as_ref())
+            .and_then(|text| text.as_str())
+            .map(ToOwned::to_owned);
+
+        if !displayed {
+            let execute_result = ExecuteResult {
+                execution_count,
+                data: render.data,
+                metadata: render.metadata,
+            };
+            let broadcast = IopubBroacast::from(execute_result);
+            let broadcast = Message {
+                zmq_identities: message.zmq_identities.clone(),
+                header: Header::new(broadcast.msg_type()),
+                parent_header: Some(message.header.clone()),
+                metadata: Metadata::empty(),
+                content: broadcast,
+                buffers: vec![],
+            };
+            ctx.iopub.send(broadcast.into_multipart()?).await?;
+        }
+    }
+
+    record_history(ctx, request, execution_count, output);
+
+    let user_expressions = evaluate_user_expressions(ctx, &request.user_expressions);
     let reply = ExecuteReply {
         execution_count,
-        user_expressions: json!({}),
+        user_expressions,
     };
     let reply = ShellReply::Ok(ShellReplyOk::Execute(reply));
     let reply = Message {
         zmq_identities: message.zmq_identities.clone(),
-        header: Header::new(msg_type),
+        header: Header::new(message.header.reply_type()?),
         parent_header: Some(message.header.clone()),
         metadata: Metadata::empty(),
         content: reply,
         buffers: vec![],
     };
-    reply
-        .into_multipart()
-        .unwrap()
-        .send(&mut ctx.socket)
-        .await
-        .unwrap();
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
+}
+
+/// Silent requests are never stored in the history.
+fn stores_history(request: &ExecuteRequest) -> bool {
+    request.store_history && !request.silent
+}
+
+/// Record an execution in the history, the output is only stored if enabled
+/// via `nuju settings`.
+fn record_history(
+    ctx: &HandlerContext,
+    request: &ExecuteRequest,
+    execution_count: usize,
+    output: Option<String>,
+) {
+    if !stores_history(request) {
+        return;
+    }
+    let output = output.filter(|_| ctx.settings.lock().history.output);
+    ctx.history
+        .lock()
+        .record(execution_count, request.code.clone(), output);
+}
+
+/// Evaluate the user expressions against the state after the execution.
+///
+/// Every expression runs on a copy of the state, so that definitions and
+/// assignments don't leak into the session.
+fn evaluate_user_expressions(
+    ctx: &HandlerContext,
+    user_expressions: &HashMap<String, String>,
+) -> HashMap<String, UserExpression> {
+    let renderers = ctx.renderers.lock().shell(ctx.subshell_id.as_deref());
+    user_expressions
+        .iter()
+        .map(|(name, code)| {
+            let mut engine_state = ctx.engine_state.clone();
+            let mut stack = ctx.stack.clone();
+            let source_name = format!("user_expressions[{name}]");
+            // expressions may run for a while, they are interrupted like executions
+            let executed = run_blocking(|| {
+                let data = nu::execute(code, &mut engine_state, &mut stack, &source_name)?;
+                Ok(PipelineRender::render(
+                    data,
+                    &engine_state,
+                    &mut stack,
+                    &ctx.spans,
+                    ctx.format_decl_ids,
+                    &renderers,
+                    &ctx.settings,
+                    None,
+                ))
+            });
+            let expression = match executed {
+                Ok(Ok(render)) => {
+                    let render = StringifiedPipelineRender::from(render);
+                    UserExpression::Ok {
+                        data: render.data,
+                        metadata: render.metadata,
+                    }
+                }
+                Ok(Err(err)) => UserExpression::Error {
+                    ename: "nu-jupyter-kernel::render-error".to_owned(),
+                    evalue: err.to_string(),
+                    traceback: vec![],
+                },
+                Err(error) => {
+                    let mut working_set = StateWorkingSet::new(&engine_state);
+                    let report = ReportExecuteError::new(error, &mut working_set);
+                    UserExpression::Error {
+                        ename: report.code().to_string(),
+                        evalue: report.to_string(),
+                        traceback: report.traceback(),
+                    }
+                }
+            };
+            (name.clone(), expression)
+        })
+        .collect()
 }
 
 async fn handle_is_complete_request(
     ctx: &mut HandlerContext,
     message: &Message<ShellRequest>,
     request: &IsCompleteRequest,
-) {
-    let reply = IsCompleteReply::Unknown;
+) -> Result<(), MessageError> {
+    let reply = nu::is_complete::is_complete(&ctx.engine_state, &request.code);
     let reply = ShellReply::Ok(ShellReplyOk::IsComplete(reply));
-    let msg_type = ShellReply::msg_type(&message.header.msg_type).unwrap();
+    let msg_type = message.header.reply_type()?;
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
+}
+
+async fn handle_complete_request(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    request: &CompleteRequest,
+) -> Result<(), MessageError> {
+    let completions = nu::completion::complete(
+        &ctx.engine_state,
+        &ctx.stack,
+        &request.code,
+        request.cursor_pos,
+    );
+
+    let types = completions
+        .matches
+        .iter()
+        .map(|m| CompleteReplyType {
+            start: completions.cursor_start,
+            end: completions.cursor_end,
+            text: m.text.clone(),
+            kind: m.type_name().to_owned(),
+            signature: m.description.clone().unwrap_or_default(),
+        })
+        .collect();
+    let reply = CompleteReply {
+        matches: completions.matches.into_iter().map(|m| m.text).collect(),
+        cursor_start: completions.cursor_start,
+        cursor_end: completions.cursor_end,
+        metadata: CompleteReplyMetadata { types },
+    };
+    let reply = ShellReply::Ok(ShellReplyOk::Complete(reply));
+    let msg_type = message.header.reply_type()?;
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
+}
+
+async fn handle_inspect_request(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    request: &InspectRequest,
+) -> Result<(), MessageError> {
+    let inspection = nu::inspect::inspect(
+        &ctx.engine_state,
+        &mut ctx.stack,
+        &request.code,
+        request.cursor_pos,
+    );
+    let renderers = ctx.renderers.lock().shell(ctx.subshell_id.as_deref());
+    let data = inspection.and_then(|inspection| {
+        inspection
+            .render(
+                &ctx.engine_state,
+                &mut ctx.stack,
+                &ctx.spans,
+                ctx.format_decl_ids,
+                &renderers,
+                &ctx.settings,
+            )
+            .inspect_err(|err| eprintln!("could not render inspection: {err}"))
+            .ok()
+    });
+
+    let reply = InspectReply {
+        found: data.is_some(),
+        data: data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+        metadata: HashMap::new(),
+    };
+    let reply = ShellReply::Ok(ShellReplyOk::Inspect(reply));
+    let msg_type = message.header.reply_type()?;
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
+}
+
+async fn handle_comm_info_request(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    request: &CommInfoRequest,
+) -> Result<(), MessageError> {
+    let comms = ctx.comms.lock().comms(request.target_name.as_deref());
+    let reply = ShellReply::Ok(ShellReplyOk::CommInfo(CommInfoReply { comms }));
+    let msg_type = message.header.reply_type()?;
+    let reply = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(msg_type),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: reply,
+        buffers: vec![],
+    };
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
+}
+
+/// Open a comm for the frontend and pass the data sent when opening to the
+/// handler of its target.
+async fn handle_comm_open(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    comm_open: &CommOpen,
+) -> Result<(), MessageError> {
+    let accepted = {
+        let mut comms = ctx.comms.lock();
+        let accepted = comms.accepts_target(&comm_open.target_name);
+        if accepted {
+            comms.open(comm_open.comm_id.clone(), comm_open.target_name.clone());
+        }
+        accepted
+    };
+    if accepted {
+        run_comm_handler(ctx, message, &comm_open.comm_id, &comm_open.data).await;
+        return Ok(());
+    }
+
+    // the spec demands to close comms for unknown targets immediately
+    eprintln!("no comm handler for target {:?}", comm_open.target_name);
+    let broadcast = IopubBroacast::CommClose(CommClose {
+        comm_id: comm_open.comm_id.clone(),
+        data: json!({}),
+    });
+    let broadcast = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(broadcast.msg_type()),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: broadcast,
+        buffers: vec![],
+    };
+    ctx.iopub.send(broadcast.into_multipart()?).await?;
+    Ok(())
+}
+
+/// Call the handler of a comm with data the frontend sent, either when
+/// opening the comm or via a comm message.
+async fn run_comm_handler(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    comm_id: &str,
+    data: &serde_json::Value,
+) {
+    let Some((handler, target_name)) = ctx.comms.lock().handler(comm_id)
+    else {
+        eprintln!("no comm handler for comm {comm_id:?}");
+        return;
+    };
+    // comm messages arrive at the main shell, but the handler only exists in
+    // the engine state of the shell that registered it
+    if handler.subshell_id != ctx.subshell_id {
+        let owner = handler.subshell_id.as_deref();
+        if !ctx.subshells.forward(owner, message.clone()) {
+            eprintln!("shell of the comm handler for comm {comm_id:?} is gone");
+        }
+        return;
+    }
+    let mut closure = handler.closure;
+
+    ctx.konst
+        .update_message(&mut ctx.stack, message.clone(), ctx.subshell_id.clone());
+    ctx.stdout_handler.update_reply(
+        message.zmq_identities.clone(),
+        message.header.clone(),
+        false,
+    );
+    ctx.stderr_handler.update_reply(
+        message.zmq_identities.clone(),
+        message.header.clone(),
+        false,
+    );
+
+    // closures only see their captures, so `$nuju` has to be passed explicitly
+    let konst_id = ctx.konst.var_id();
+    let konst = match ctx.stack.get_var(konst_id, Span::unknown()) {
+        Ok(konst) => konst,
+        Err(err) => {
+            publish_comm_error(ctx, message, err).await;
+            return;
+        }
+    };
+    closure.captures.retain(|(var_id, _)| *var_id != konst_id);
+    closure.captures.push((konst_id, konst));
+
+    let comm_message = CommMessage {
+        comm_id: comm_id.to_owned(),
+        target_name,
+        data: json_to_value(data.clone(), Span::unknown()),
+    };
+    let executed = run_blocking(|| {
+        ClosureEvalOnce::new_preserve_out_dest(&ctx.engine_state, &ctx.stack, closure)
+            .run_with_value(comm_message.into_value(Span::unknown()))
+            .and_then(|data| data.drain())
+    });
+
+    ctx.engine_state.reset_signals();
+    if let Err(err) = executed {
+        publish_comm_error(ctx, message, err).await;
+    }
+}
+
+/// Publish the error of a comm handler, comm messages have no reply to carry
+/// it.
+async fn publish_comm_error(
+    ctx: &HandlerContext,
+    message: &Message<ShellRequest>,
+    error: ShellError,
+) {
+    let mut working_set = StateWorkingSet::new(&ctx.engine_state);
+    let broadcast = {
+        // keeping the report makes the following part not Send
+        let report = ReportExecuteError::new(error.into(), &mut working_set);
+        IopubBroacast::Error(iopub::Error {
+            name: report.code().to_string(),
+            value: report.to_string(),
+            traceback: report.traceback(),
+        })
+    };
+    let broadcast = Message {
+        zmq_identities: message.zmq_identities.clone(),
+        header: Header::new(broadcast.msg_type()),
+        parent_header: Some(message.header.clone()),
+        metadata: Metadata::empty(),
+        content: broadcast,
+        buffers: vec![],
+    };
+    let sent = match broadcast.into_multipart() {
+        Ok(multipart) => ctx.iopub.send(multipart).await.map_err(MessageError::from),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = sent {
+        eprintln!("could not publish comm handler error: {err}");
+    }
+}
+
+async fn handle_history_request(
+    ctx: &mut HandlerContext,
+    message: &Message<ShellRequest>,
+    request: &HistoryRequest,
+) -> Result<(), MessageError> {
+    let entries: Vec<HistoryEntry> = {
+        let history = ctx.history.lock();
+        let entries = match &request.access {
+            HistoryAccess::Range {
+                session,
+                start,
+                stop,
+            } => history.range(*session, *start, *stop),
+            HistoryAccess::Tail { n } => history.tail(*n),
+            HistoryAccess::Search { pattern, unique, n } => {
+                history.search(pattern, *unique, *n).unwrap_or_else(|err| {
+                    eprintln!("invalid history search pattern: {err}");
+                    vec![]
+                })
+            }
+        };
+        entries.into_iter().cloned().collect()
+    };
+    let history = entries
+        .into_iter()
+        .map(
+            |HistoryEntry {
+                 session,
+                 line,
+                 input,
+                 output,
+             }| match request.output {
+                true => HistoryItem::InputOutput(session, line, (input, output)),
+                false => HistoryItem::Input(session, line, input),
+            },
+        )
+        .collect();
+
+    let reply = ShellReply::Ok(ShellReplyOk::History(HistoryReply { history }));
+    let msg_type = message.header.reply_type()?;
     let reply = Message {
         zmq_identities: message.zmq_identities.clone(),
         header: Header::new(msg_type),
@@ -357,10 +1360,6 @@ async fn handle_is_complete_request(
         content: reply,
         buffers: vec![],
     };
-    reply
-        .into_multipart()
-        .unwrap()
-        .send(&mut ctx.socket)
-        .await
-        .unwrap();
+    ctx.shell.send(reply.into_multipart()?).await?;
+    Ok(())
 }
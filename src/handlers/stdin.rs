@@ -0,0 +1,68 @@
+use std::sync::mpsc::SyncSender;
+
+use tokio::sync::{broadcast, mpsc};
+
+use super::ShutdownSignal;
+use crate::StdinSocket;
+use crate::jupyter::messages::Message;
+use crate::jupyter::messages::multipart::Multipart;
+use crate::jupyter::messages::stdin::StdinReply;
+
+/// Request to prompt the frontend for input.
+///
+/// The value of the `input_reply` is sent back via `reply`.
+/// The reply is sent via a std channel as the requesting command runs on a
+/// blocking thread.
+pub struct Prompt {
+    pub request: Multipart,
+    pub reply: SyncSender<String>,
+}
+
+pub async fn handle(
+    mut socket: StdinSocket,
+    mut shutdown: broadcast::Receiver<ShutdownSignal>,
+    mut prompt_rx: mpsc::Receiver<Prompt>,
+) {
+    // only the latest prompt is waiting for a reply, older ones were interrupted
+    let mut pending: Option<SyncSender<String>> = None;
+
+    loop {
+        tokio::select! {
+            biased;
+            v = shutdown.recv() => match v {
+                Ok(signal) => {
+                    signal.done();
+                    match signal.restart {
+                        true => pending = None,
+                        false => break,
+                    }
+                }
+                Err(_) => break,
+            },
+            v = prompt_rx.recv() => {
+                let Some(Prompt { request, reply }) = v else { break };
+                // without a request the prompting command waits until interrupted
+                if let Err(err) = request.send(&mut socket).await {
+                    eprintln!("could not send input_request: {err}");
+                    continue;
+                }
+                pending = Some(reply);
+            }
+            v = Message::<StdinReply>::recv(&mut socket) => {
+                let message = match v {
+                    Ok(message) => message,
+                    Err(err) => {
+                        eprintln!("could not recv stdin message: {err}");
+                        continue;
+                    }
+                };
+                let StdinReply::Input(input_reply) = message.content;
+                match pending.take() {
+                    // the prompting command may be gone already, e.g. after an interrupt
+                    Some(reply) => _ = reply.send(input_reply.value),
+                    None => eprintln!("received input_reply without pending input_request"),
+                }
+            }
+        }
+    }
+}
@@ -14,8 +14,17 @@ use crate::jupyter::messages::{Header, Message, Metadata};
 
 const BUFFER_SIZE: usize = 8 * 1024;
 
+/// Where stream output is currently sent to.
+#[derive(Debug, Clone, Default)]
+struct ReplyTarget {
+    zmq_identities: Vec<Bytes>,
+    parent_header: Option<Header>,
+    /// Silent requests don't publish any output.
+    silent: bool,
+}
+
 pub struct StreamHandler {
-    message_data: Arc<Mutex<(Vec<Bytes>, Option<Header>)>>,
+    message_data: Arc<Mutex<ReplyTarget>>,
     stream_name: iopub::StreamName, // iopub_tx: Sender<Multipart>, // moved into the thread
 }
 
@@ -26,7 +35,7 @@ impl StreamHandler {
     ) -> io::Result<(Self, File)> {
         // TODO: construct a Self, create a pipe, start a reader thread, return the
         // writer as a file
-        let message_data = Arc::new(Mutex::new((vec![], None)));
+        let message_data = Arc::new(Mutex::new(ReplyTarget::default()));
 
         let (mut pipe_reader, pipe_writer) = os_pipe::pipe()?;
         let t_message_data = message_data.clone();
@@ -38,8 +47,12 @@ impl StreamHandler {
                     let mut s_buf: Vec<u8> = Vec::new();
                     loop {
                         match pipe_reader.read(&mut read_buf) {
-                            Err(err) => todo!("handle that error"),
-                            Ok(0) if s_buf.is_empty() => todo!("stream is dead"),
+                            Err(err) => {
+                                eprintln!("could not read {}: {err}", stream_name.as_ref());
+                                return;
+                            }
+                            // all writers are closed
+                            Ok(0) if s_buf.is_empty() => return,
                             Ok(0) => break,
                             Ok(BUFFER_SIZE) => s_buf.extend_from_slice(&read_buf),
                             Ok(n) => {
@@ -48,13 +61,19 @@ impl StreamHandler {
                             }
                         }
                     }
-                    // TODO: handle this somehow
-                    let s = String::from_utf8(s_buf).unwrap();
+                    let s = String::from_utf8_lossy(&s_buf).into_owned();
                     let broadcast = IopubBroacast::Stream(iopub::Stream {
                         name: stream_name,
                         text: s,
                     });
-                    let (zmq_identities, parent_header) = t_message_data.lock().clone();
+                    let ReplyTarget {
+                        zmq_identities,
+                        parent_header,
+                        silent,
+                    } = t_message_data.lock().clone();
+                    if silent {
+                        continue;
+                    }
                     let message = Message {
                         zmq_identities,
                         header: Header::new(broadcast.msg_type()),
@@ -63,10 +82,18 @@ impl StreamHandler {
                         content: broadcast,
                         buffers: vec![],
                     };
-                    // TODO: handle this better, also does this need to be blocking?
-                    iopub_tx
-                        .blocking_send(message.into_multipart().unwrap())
-                        .unwrap();
+                    let multipart = match message.into_multipart() {
+                        Ok(multipart) => multipart,
+                        Err(err) => {
+                            eprintln!("could not send {}: {err}", stream_name.as_ref());
+                            continue;
+                        }
+                    };
+                    // TODO: does this need to be blocking?
+                    if iopub_tx.blocking_send(multipart).is_err() {
+                        // iopub handler is gone, the kernel is shutting down
+                        return;
+                    }
                 }
             })?;
 
@@ -84,9 +111,16 @@ impl StreamHandler {
         ))
     }
 
-    pub fn update_reply(&mut self, zmq_identities: Vec<Bytes>, parent_header: Header) {
-        let mut message_data = self.message_data.lock();
-        message_data.0 = zmq_identities;
-        message_data.1 = Some(parent_header);
+    pub fn update_reply(
+        &mut self,
+        zmq_identities: Vec<Bytes>,
+        parent_header: Header,
+        silent: bool,
+    ) {
+        *self.message_data.lock() = ReplyTarget {
+            zmq_identities,
+            parent_header: Some(parent_header),
+            silent,
+        };
     }
 }
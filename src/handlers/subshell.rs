@@ -0,0 +1,181 @@
+//! Subshells (JEP 91) handle shell requests concurrently to the main shell.
+//!
+//! All shell requests arrive on the same socket, the [router](route) passes
+//! each request to the execution loop named by the `subshell_id` of its
+//! header.
+//! Every subshell has its own `Stack` and starts from the state of the main
+//! shell at its creation, a running execution of the main shell is finished
+//! first.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+use super::ShutdownSignal;
+use super::shell::{self, SubshellBase};
+use crate::ShellSocket;
+use crate::error::MessageError;
+use crate::jupyter::messages::Message;
+use crate::jupyter::messages::multipart::Multipart;
+use crate::jupyter::messages::shell::ShellRequest;
+
+/// Shell request as received by the router.
+pub type ShellMessage = Result<Message<ShellRequest>, MessageError>;
+
+/// Request to the main shell for its current state, see
+/// [`HandlerContext::base`](shell::HandlerContext::base).
+pub type BaseRequest = oneshot::Sender<SubshellBase>;
+
+/// Registry of the running subshells.
+pub struct Subshells {
+    main: mpsc::UnboundedSender<ShellMessage>,
+    subshells: Mutex<HashMap<String, mpsc::UnboundedSender<ShellMessage>>>,
+    /// The main shell answers these between its requests, so its state is only
+    /// copied when a subshell is created.
+    base_requests: mpsc::UnboundedSender<BaseRequest>,
+    shutdown: broadcast::Sender<ShutdownSignal>,
+}
+
+impl Subshells {
+    pub fn new(
+        main: mpsc::UnboundedSender<ShellMessage>,
+        base_requests: mpsc::UnboundedSender<BaseRequest>,
+        shutdown: broadcast::Sender<ShutdownSignal>,
+    ) -> Self {
+        Subshells {
+            main,
+            subshells: Mutex::new(HashMap::new()),
+            base_requests,
+            shutdown,
+        }
+    }
+
+    /// Start a new subshell and return its id.
+    ///
+    /// Waits for the main shell to finish its current request.
+    pub async fn create(self: &Arc<Self>) -> io::Result<String> {
+        let main_gone = || io::Error::other("main shell is gone");
+        let (base_tx, base_rx) = oneshot::channel();
+        self.base_requests.send(base_tx).map_err(|_| main_gone())?;
+        let base = base_rx.await.map_err(|_| main_gone())?;
+
+        let id = Uuid::new_v4().to_string();
+        let ctx = base.start(id.clone(), self.clone())?;
+        let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+        self.subshells.lock().insert(id.clone(), requests_tx);
+        tokio::spawn(shell::handle(
+            ctx,
+            self.shutdown.subscribe(),
+            requests_rx,
+            None,
+        ));
+        Ok(id)
+    }
+
+    /// Delete a subshell, it stops after its current request.
+    ///
+    /// Returns `false` if no subshell with this id exists.
+    pub fn delete(&self, id: &str) -> bool {
+        self.subshells.lock().remove(id).is_some()
+    }
+
+    /// Ids of all running subshells.
+    pub fn list(&self) -> Vec<String> {
+        let mut ids: Vec<_> = self.subshells.lock().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Delete all subshells.
+    pub fn clear(&self) {
+        self.subshells.lock().clear();
+    }
+
+    /// Pass a message to the loop of a shell other than the one named in its
+    /// header, `None` for the main shell.
+    ///
+    /// Returns `false` if the shell is gone.
+    pub fn forward(&self, subshell_id: Option<&str>, message: Message<ShellRequest>) -> bool {
+        let sent = match subshell_id {
+            None => self.main.send(Ok(message)),
+            Some(id) => match self.subshells.lock().get(id) {
+                Some(subshell) => subshell.send(Ok(message)),
+                None => return false,
+            },
+        };
+        sent.is_ok()
+    }
+
+    /// Pass a message to the loop of its subshell.
+    ///
+    /// Requests for unknown subshells are passed to the main shell as an
+    /// error, so that they still get a reply.
+    fn dispatch(&self, message: ShellMessage) {
+        let subshell_id = match &message {
+            Ok(message) => message.header.subshell_id.clone(),
+            Err(err) => err
+                .request()
+                .and_then(|request| request.header.subshell_id.clone()),
+        };
+        let message = match subshell_id {
+            None => message,
+            Some(id) => match self.subshells.lock().get(&id) {
+                Some(subshell) => {
+                    if subshell.send(message).is_err() {
+                        eprintln!("subshell {id} is gone");
+                    }
+                    return;
+                }
+                None => match message {
+                    Ok(message) => Err(MessageError::UnknownSubshell(Box::new(
+                        message.with_content(()),
+                    ))),
+                    Err(err) => Err(err),
+                },
+            },
+        };
+        if self.main.send(message).is_err() {
+            eprintln!("main shell is gone");
+        }
+    }
+}
+
+/// Receive shell requests for all shells and send their replies.
+pub async fn route(
+    mut socket: ShellSocket,
+    mut shutdown: broadcast::Receiver<ShutdownSignal>,
+    subshells: Arc<Subshells>,
+    mut replies: mpsc::Receiver<Multipart>,
+) {
+    loop {
+        tokio::select! {
+            biased;
+            v = shutdown.recv() => match v {
+                Ok(signal) => {
+                    // subshells don't survive restarts
+                    subshells.clear();
+                    signal.done();
+                    if !signal.restart {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            },
+            v = replies.recv() => {
+                // all shells are gone, nothing can be replied anymore
+                let Some(reply) = v
+                else {
+                    break;
+                };
+                if let Err(err) = reply.send(&mut socket).await {
+                    eprintln!("could not send shell reply: {err}");
+                }
+            }
+            v = Message::<ShellRequest>::recv(&mut socket) => subshells.dispatch(v),
+        }
+    }
+}
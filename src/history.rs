@@ -0,0 +1,279 @@
+//! Persistent history of executed cells.
+//!
+//! Every kernel start is a new session, entries are appended to a JSON lines
+//! file in the local data directory so that the history survives restarts.
+//! Kernels running at the same time share the file, it is locked while
+//! sessions are allocated and entries are written.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::{env, iter};
+
+use nu_glob::Pattern;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable to store the history in another file.
+pub const PATH_ENV_VAR: &str = "NU_JUPYTER_KERNEL_HISTORY";
+
+/// Entries kept in the history file, older ones are removed when a session
+/// starts.
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub session: u32,
+    pub line: usize,
+    pub input: String,
+    pub output: Option<String>,
+}
+
+/// Line of the history file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Entry(HistoryEntry),
+    /// Marks a session as taken, even before it has any entries.
+    Start {
+        session: u32,
+    },
+}
+
+#[derive(Debug)]
+pub struct History {
+    session: u32,
+    entries: Vec<HistoryEntry>,
+    /// The file entries are appended to, `None` if the history is only kept
+    /// in memory.
+    file: Option<File>,
+}
+
+impl History {
+    /// Path of the history file, `None` if no local data directory exists.
+    ///
+    /// Can be overridden via [`PATH_ENV_VAR`].
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os(PATH_ENV_VAR) {
+            return Some(path.into());
+        }
+
+        let mut path = dirs::data_local_dir()?;
+        path.push("nu-jupyter-kernel");
+        path.push("history.jsonl");
+        Some(path)
+    }
+
+    /// Load the history and start a new session.
+    ///
+    /// If the history file cannot be used, the history is only kept in memory.
+    pub fn load() -> Self {
+        Self::load_from(Self::path().as_deref())
+    }
+
+    fn load_from(path: Option<&Path>) -> Self {
+        match path.map(Self::open) {
+            Some(Ok(history)) => history,
+            Some(Err(err)) => {
+                eprintln!("could not open history file: {err}");
+                Self::in_memory()
+            }
+            None => Self::in_memory(),
+        }
+    }
+
+    fn in_memory() -> Self {
+        Self {
+            session: 1,
+            entries: vec![],
+            file: None,
+        }
+    }
+
+    fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+
+        // other kernels must not take the same session
+        file.lock()?;
+        let lines: Vec<Line> = BufReader::new(&file)
+            .lines()
+            .map_while(Result::ok)
+            // skip corrupted lines instead of losing the whole history
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+        let session = lines
+            .iter()
+            .map(|line| match line {
+                Line::Entry(entry) => entry.session,
+                Line::Start { session } => *session,
+            })
+            .max()
+            .unwrap_or(0) +
+            1;
+        let mut entries: Vec<HistoryEntry> = lines
+            .into_iter()
+            .filter_map(|line| match line {
+                Line::Entry(entry) => Some(entry),
+                Line::Start { .. } => None,
+            })
+            .collect();
+
+        let start = Line::Start { session };
+        let written = match entries.len() > MAX_ENTRIES {
+            true => {
+                entries.drain(..entries.len() - MAX_ENTRIES);
+                file.set_len(0).and_then(|_| {
+                    let lines = entries
+                        .iter()
+                        .cloned()
+                        .map(Line::Entry)
+                        .chain(iter::once(start));
+                    write_lines(&mut file, lines)
+                })
+            }
+            false => write_lines(&mut file, iter::once(start)),
+        };
+        file.unlock()?;
+        written?;
+
+        Ok(Self {
+            session,
+            entries,
+            file: Some(file),
+        })
+    }
+
+    pub fn session(&self) -> u32 {
+        self.session
+    }
+
+    /// Record an executed cell of the current session.
+    pub fn record(&mut self, line: usize, input: String, output: Option<String>) {
+        let entry = HistoryEntry {
+            session: self.session,
+            line,
+            input,
+            output,
+        };
+        if let Some(file) = self.file.as_mut() {
+            let written = file.lock().and_then(|_| {
+                let written = write_lines(file, iter::once(Line::Entry(entry.clone())));
+                file.unlock().and(written)
+            });
+            if let Err(err) = written {
+                eprintln!("could not write history file: {err}");
+            }
+        }
+        self.entries.push(entry);
+    }
+
+    /// Entries of a session with `start <= line < stop`.
+    ///
+    /// Positive sessions are absolute, `0` is the current session and
+    /// negative sessions count back from the current one.
+    pub fn range(&self, session: i64, start: usize, stop: Option<usize>) -> Vec<&HistoryEntry> {
+        let session = match session {
+            1.. => session,
+            _ => self.session as i64 + session,
+        };
+        self.entries
+            .iter()
+            .filter(|entry| entry.session as i64 == session)
+            .filter(|entry| entry.line >= start && stop.is_none_or(|stop| entry.line < stop))
+            .collect()
+    }
+
+    /// The last `n` entries across all sessions.
+    pub fn tail(&self, n: usize) -> Vec<&HistoryEntry> {
+        let skip = self.entries.len().saturating_sub(n);
+        self.entries.iter().skip(skip).collect()
+    }
+
+    /// Entries with an input matching the glob `pattern`, limited to the last
+    /// `n` matches.
+    ///
+    /// With `unique` only the latest entry for every input is returned.
+    pub fn search(
+        &self,
+        pattern: &str,
+        unique: bool,
+        n: Option<usize>,
+    ) -> Result<Vec<&HistoryEntry>, nu_glob::PatternError> {
+        let pattern = Pattern::new(pattern)?;
+        let mut seen = HashSet::new();
+        let mut matches: Vec<_> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| pattern.matches(&entry.input))
+            .filter(|entry| !unique || seen.insert(entry.input.as_str()))
+            .take(n.unwrap_or(usize::MAX))
+            .collect();
+        matches.reverse();
+        Ok(matches)
+    }
+}
+
+/// Write all lines at once, so that a failure leaves at most one corrupted
+/// line.
+fn write_lines(file: &mut File, lines: impl Iterator<Item = Line>) -> io::Result<()> {
+    let mut json = String::new();
+    for line in lines {
+        json.push_str(&serde_json::to_string(&line).expect("line is valid json"));
+        json.push('\n');
+    }
+    file.write_all(json.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir()
+            .join(format!("nu-jupyter-kernel-{}", std::process::id()))
+            .join(name);
+        _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn concurrent_kernels_get_own_sessions() {
+        let path = temp_path("sessions.jsonl");
+        let mut first = History::load_from(Some(&path));
+        let mut second = History::load_from(Some(&path));
+        assert_eq!((first.session(), second.session()), (1, 2));
+
+        first.record(1, "1".to_owned(), None);
+        second.record(1, "2".to_owned(), None);
+        let third = History::load_from(Some(&path));
+        assert_eq!(third.session(), 3);
+        assert_eq!(third.range(1, 0, None)[0].input, "1");
+        assert_eq!(third.range(-1, 0, None)[0].input, "2");
+        assert_eq!(third.tail(5).len(), 2);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn old_entries_are_removed() {
+        let path = temp_path("capped.jsonl");
+        let mut history = History::load_from(Some(&path));
+        for line in 0..MAX_ENTRIES + 5 {
+            history.record(line, line.to_string(), None);
+        }
+
+        let history = History::load_from(Some(&path));
+        assert_eq!(history.session(), 2);
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert_eq!(history.entries[0].input, "5");
+        assert_eq!(History::load_from(Some(&path)).session(), 3);
+        fs::remove_file(path).unwrap();
+    }
+}
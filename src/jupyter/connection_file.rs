@@ -1,5 +1,5 @@
 use std::fmt::Display;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use std::path::Path;
 use std::{fs, io};
 
@@ -15,7 +15,7 @@ pub struct ConnectionFile {
     pub stdin_port: PortAddr,
     #[serde(alias = "hb_port")]
     pub heartbeat_port: PortAddr,
-    pub ip: Ipv4Addr,
+    pub ip: Host,
     pub iopub_port: PortAddr,
     #[serde(deserialize_with = "deserialize_key")]
     pub key: Vec<u8>,
@@ -35,6 +35,36 @@ impl ConnectionFile {
         let connection_file: ConnectionFile = serde_json::from_str(&contents)?;
         Ok(connection_file)
     }
+
+    /// Endpoint to bind the socket of `port` to.
+    ///
+    /// IPC endpoints use `ip` as path prefix, like ipykernel does.
+    pub fn endpoint(&self, port: &PortAddr) -> String {
+        match (self.transport, &self.ip) {
+            (Transport::Tcp, Host::Ip(IpAddr::V6(ip))) => format!("tcp://[{ip}]:{port}"),
+            (Transport::Tcp, ip) => format!("tcp://{ip}:{port}"),
+            (Transport::Ipc, path) => format!("ipc://{path}-{port}"),
+        }
+    }
+}
+
+/// The `ip` field of the connection file.
+///
+/// For TCP this is an IP address, for IPC a path prefix.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Host {
+    Ip(IpAddr),
+    Path(String),
+}
+
+impl Display for Host {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Host::Ip(ip) => write!(f, "{ip}"),
+            Host::Path(path) => write!(f, "{path}"),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,8 +102,15 @@ impl<'de> Deserialize<'de> for SignatureScheme {
         };
 
         let hash_fn = match hash_fn {
+            "sha1" => SupportedSignatureHashFunction::Sha1,
             "sha256" => SupportedSignatureHashFunction::Sha256,
-            other => return Err(serde::de::Error::unknown_variant(other, &["sha256"])),
+            "sha384" => SupportedSignatureHashFunction::Sha384,
+            "sha512" => SupportedSignatureHashFunction::Sha512,
+            other => {
+                return Err(serde::de::Error::unknown_variant(other, &[
+                    "sha1", "sha256", "sha384", "sha512",
+                ]));
+            }
         };
 
         Ok(SignatureScheme { algorithm, hash_fn })
@@ -89,19 +126,24 @@ pub enum SupportedSignatureAlgorithm {
 #[non_exhaustive]
 #[derive(Debug, Clone, Copy)]
 pub enum SupportedSignatureHashFunction {
+    Sha1,
     Sha256,
+    Sha384,
+    Sha512,
 }
 
 #[derive(Debug, Clone, Copy, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Transport {
     Tcp,
+    Ipc,
 }
 
 impl Display for Transport {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Transport::Tcp => write!(f, "tcp"),
+            Transport::Ipc => write!(f, "ipc"),
         }
     }
 }
@@ -113,3 +155,46 @@ where
     let as_str = String::deserialize(deserializer)?;
     Ok(as_str.into_bytes())
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn connection_file(transport: &str, ip: &str) -> ConnectionFile {
+        serde_json::from_value(json!({
+            "control_port": 50160,
+            "shell_port": 57503,
+            "transport": transport,
+            "signature_scheme": "hmac-sha256",
+            "stdin_port": 52597,
+            "hb_port": 42540,
+            "ip": ip,
+            "iopub_port": 40885,
+            "key": "a0436f6c-1916-498b-8eb9-e81ab9368e84",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn tcp_endpoints() {
+        let ipv4 = connection_file("tcp", "127.0.0.1");
+        assert!(matches!(ipv4.ip, Host::Ip(IpAddr::V4(_))));
+        assert_eq!(ipv4.endpoint(&ipv4.shell_port), "tcp://127.0.0.1:57503");
+
+        let ipv6 = connection_file("tcp", "::1");
+        assert!(matches!(ipv6.ip, Host::Ip(IpAddr::V6(_))));
+        assert_eq!(ipv6.endpoint(&ipv6.heartbeat_port), "tcp://[::1]:42540");
+    }
+
+    #[test]
+    fn ipc_endpoints() {
+        let ipc = connection_file("ipc", "/tmp/kernel-ipc");
+        assert!(matches!(&ipc.ip, Host::Path(path) if path == "/tmp/kernel-ipc"));
+        assert_eq!(
+            ipc.endpoint(&ipc.control_port),
+            "ipc:///tmp/kernel-ipc-50160"
+        );
+    }
+}
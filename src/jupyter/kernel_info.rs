@@ -10,6 +10,7 @@ pub struct KernelInfo {
     pub language_info: LanguageInfo,
     pub banner: String,
     pub debugger: bool,
+    pub supported_features: Vec<String>,
     pub help_links: Vec<HelpLink>,
 }
 
@@ -58,7 +59,8 @@ impl KernelInfo {
                 file_extension: ".nu".to_owned(),
             },
             banner: include_str!("../../banner.txt").to_owned(),
-            debugger: false,
+            debugger: true,
+            supported_features: vec!["kernel subshells".to_owned()],
             help_links: [
                 ("Discord", "https://discord.gg/NtAbbGn"),
                 ("GitHub", "https://github.com/nushell/nushell"),
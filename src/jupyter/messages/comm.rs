@@ -0,0 +1,45 @@
+//! Comm messages are sent in both directions.
+//!
+//! The frontend sends them on the shell channel, the kernel publishes them on
+//! the iopub channel.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommOpen {
+    pub comm_id: String,
+    pub target_name: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommMsg {
+    pub comm_id: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommClose {
+    pub comm_id: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CommInfoRequest {
+    pub target_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CommInfoReply {
+    pub comms: HashMap<String, CommInfo>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CommInfo {
+    pub target_name: String,
+}
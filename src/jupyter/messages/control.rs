@@ -2,29 +2,39 @@ use serde::{Deserialize, Serialize};
 
 use crate::jupyter::Shutdown;
 use crate::jupyter::kernel_info::KernelInfo;
+use crate::jupyter::messages::debug::{DebugRequest, DebugResponse};
+use crate::jupyter::messages::message_type::MessageType;
+use crate::jupyter::usage::ResourceUsage;
 
 #[derive(Debug, Deserialize, Clone)]
 pub enum ControlRequest {
     KernelInfo,
     Shutdown(Shutdown),
     Interrupt,
-    Debug, // TODO: check if this is reasonable
+    Debug(DebugRequest),
+    CreateSubshell,
+    DeleteSubshell(DeleteSubshellRequest),
+    ListSubshell,
+    Usage,
 }
 
 impl ControlRequest {
-    pub fn parse_variant(variant: &str, body: &str) -> Result<Self, ()> {
-        match variant {
-            "kernel_info_request" => Ok(Self::KernelInfo),
-            "shutdown_request" => Ok(Self::Shutdown(serde_json::from_str(body).unwrap())),
-            "interrupt_request" => Ok(Self::Interrupt),
-            "debug_request" => todo!(),
-            _ => {
-                eprintln!("found it here: {variant}");
-
-                eprintln!("unknown request {variant}");
-                Err(())
-            }
-        }
+    /// Parse the content of a request, `None` if the request type is unknown.
+    pub fn parse_variant(
+        variant: &MessageType,
+        body: &str,
+    ) -> Result<Option<Self>, serde_json::Error> {
+        Ok(Some(match variant {
+            MessageType::KernelInfoRequest => Self::KernelInfo,
+            MessageType::ShutdownRequest => Self::Shutdown(serde_json::from_str(body)?),
+            MessageType::InterruptRequest => Self::Interrupt,
+            MessageType::DebugRequest => Self::Debug(serde_json::from_str(body)?),
+            MessageType::CreateSubshellRequest => Self::CreateSubshell,
+            MessageType::DeleteSubshellRequest => Self::DeleteSubshell(serde_json::from_str(body)?),
+            MessageType::ListSubshellRequest => Self::ListSubshell,
+            MessageType::UsageRequest => Self::Usage,
+            _ => return Ok(None),
+        }))
     }
 }
 
@@ -41,23 +51,30 @@ pub enum ControlReply {
     },
 }
 
-impl ControlReply {
-    pub fn msg_type(request_type: &'_ str) -> Result<&'static str, ()> {
-        Ok(match request_type {
-            "kernel_info_request" => "kernel_info_reply",
-            "shutdown_request" => "shutdown_reply",
-            "interrupt_request" => "interrupt_reply",
-            "debug_request" => "debug_reply",
-            _ => todo!("handle unknown requests"),
-        })
-    }
-}
-
 #[derive(Debug, Serialize, Clone)]
 #[serde(untagged)]
 pub enum ControlReplyOk {
     KernelInfo(Box<KernelInfo>),
     Shutdown(Shutdown),
     Interrupt,
-    Debug,
+    Debug(DebugResponse),
+    CreateSubshell(CreateSubshellReply),
+    DeleteSubshell,
+    ListSubshell(ListSubshellReply),
+    Usage(Box<ResourceUsage>),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeleteSubshellRequest {
+    pub subshell_id: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CreateSubshellReply {
+    pub subshell_id: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ListSubshellReply {
+    pub subshell_id: Vec<String>,
 }
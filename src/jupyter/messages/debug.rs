@@ -0,0 +1,32 @@
+//! Messages of the Debug Adapter Protocol, wrapped in `debug_request`,
+//! `debug_reply` and `debug_event`.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DebugRequest {
+    pub seq: usize,
+    pub command: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", rename = "response")]
+pub struct DebugResponse {
+    pub seq: usize,
+    pub request_seq: usize,
+    pub success: bool,
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", rename = "event")]
+pub struct DebugEvent {
+    pub seq: usize,
+    pub event: String,
+    pub body: serde_json::Value,
+}
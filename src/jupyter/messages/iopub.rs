@@ -5,6 +5,9 @@ use serde::Serialize;
 use strum::AsRefStr;
 
 use super::Header;
+use super::comm::{CommClose, CommMsg, CommOpen};
+use super::debug::DebugEvent;
+use super::message_type::MessageType;
 use crate::jupyter::messages::{Message, Metadata};
 
 #[derive(Debug, Serialize, From, Clone)]
@@ -12,27 +15,34 @@ use crate::jupyter::messages::{Message, Metadata};
 pub enum IopubBroacast {
     Stream(Stream),
     DisplayData(DisplayData),
-    UpdateDisplayData,
-    ExecuteInput,
+    #[from(ignore)]
+    UpdateDisplayData(DisplayData),
+    ExecuteInput(ExecuteInput),
     ExecuteResult(ExecuteResult),
     Error(Error),
     Status(Status),
-    ClearOutput,
-    DebugEvent,
+    ClearOutput(ClearOutput),
+    DebugEvent(DebugEvent),
+    CommOpen(CommOpen),
+    CommMsg(CommMsg),
+    CommClose(CommClose),
 }
 
 impl IopubBroacast {
-    pub fn msg_type(&self) -> &'static str {
+    pub fn msg_type(&self) -> MessageType {
         match self {
-            IopubBroacast::Stream(_) => "stream",
-            IopubBroacast::DisplayData(_) => "display_data",
-            IopubBroacast::UpdateDisplayData => "update_display_data",
-            IopubBroacast::ExecuteInput => "execute_input",
-            IopubBroacast::ExecuteResult(_) => "execute_result",
-            IopubBroacast::Error(_) => "error",
-            IopubBroacast::Status(_) => "status",
-            IopubBroacast::ClearOutput => "clear_output",
-            IopubBroacast::DebugEvent => "debug_event",
+            IopubBroacast::Stream(_) => MessageType::Stream,
+            IopubBroacast::DisplayData(_) => MessageType::DisplayData,
+            IopubBroacast::UpdateDisplayData(_) => MessageType::UpdateDisplayData,
+            IopubBroacast::ExecuteInput(_) => MessageType::ExecuteInput,
+            IopubBroacast::ExecuteResult(_) => MessageType::ExecuteResult,
+            IopubBroacast::Error(_) => MessageType::Error,
+            IopubBroacast::Status(_) => MessageType::Status,
+            IopubBroacast::ClearOutput(_) => MessageType::ClearOutput,
+            IopubBroacast::DebugEvent(_) => MessageType::DebugEvent,
+            IopubBroacast::CommOpen(_) => MessageType::CommOpen,
+            IopubBroacast::CommMsg(_) => MessageType::CommMsg,
+            IopubBroacast::CommClose(_) => MessageType::CommClose,
         }
     }
 }
@@ -52,16 +62,22 @@ pub struct Stream {
 
 #[derive(Debug, Serialize, Clone)]
 pub struct DisplayData {
-    pub data: HashMap<String, String>,
-    pub metadata: HashMap<String, String>,
+    pub data: HashMap<String, serde_json::Value>,
+    pub metadata: HashMap<String, serde_json::Value>,
     pub transient: HashMap<String, String>,
 }
 
+#[derive(Debug, Serialize, Clone)]
+pub struct ExecuteInput {
+    pub code: String,
+    pub execution_count: usize,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct ExecuteResult {
     pub execution_count: usize,
-    pub data: HashMap<String, String>,
-    pub metadata: HashMap<String, String>,
+    pub data: HashMap<String, serde_json::Value>,
+    pub metadata: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -75,6 +91,12 @@ pub struct Error {
     pub traceback: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct ClearOutput {
+    /// Wait to clear the output until new output is available.
+    pub wait: bool,
+}
+
 #[derive(Debug, Serialize, Clone, Copy)]
 #[serde(tag = "execution_state", rename_all = "snake_case")]
 pub enum Status {
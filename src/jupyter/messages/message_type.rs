@@ -0,0 +1,206 @@
+use std::fmt;
+
+use nu_protocol::{FromValue, IntoValue, ShellError, Span, Value};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+macro_rules! create_message_types {
+    ($($variant:ident : $name:literal),+ $(,)?) => {
+        /// Type of a message, sent as `msg_type` in the header.
+        ///
+        /// Types the kernel doesn't know are kept as [`Unknown`](Self::Unknown),
+        /// so that their requests can still be answered with an error.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum MessageType {
+            $($variant,)+
+            Unknown(String),
+        }
+
+        impl MessageType {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => $name,)+
+                    Self::Unknown(name) => name,
+                }
+            }
+        }
+
+        impl From<&str> for MessageType {
+            fn from(name: &str) -> Self {
+                match name {
+                    $($name => Self::$variant,)+
+                    name => Self::Unknown(name.to_owned()),
+                }
+            }
+        }
+    };
+}
+
+create_message_types! {
+    // shell
+    ExecuteRequest: "execute_request",
+    ExecuteReply: "execute_reply",
+    InspectRequest: "inspect_request",
+    InspectReply: "inspect_reply",
+    CompleteRequest: "complete_request",
+    CompleteReply: "complete_reply",
+    HistoryRequest: "history_request",
+    HistoryReply: "history_reply",
+    IsCompleteRequest: "is_complete_request",
+    IsCompleteReply: "is_complete_reply",
+    CommInfoRequest: "comm_info_request",
+    CommInfoReply: "comm_info_reply",
+    KernelInfoRequest: "kernel_info_request",
+    KernelInfoReply: "kernel_info_reply",
+
+    // control
+    ShutdownRequest: "shutdown_request",
+    ShutdownReply: "shutdown_reply",
+    InterruptRequest: "interrupt_request",
+    InterruptReply: "interrupt_reply",
+    DebugRequest: "debug_request",
+    DebugReply: "debug_reply",
+    CreateSubshellRequest: "create_subshell_request",
+    CreateSubshellReply: "create_subshell_reply",
+    DeleteSubshellRequest: "delete_subshell_request",
+    DeleteSubshellReply: "delete_subshell_reply",
+    ListSubshellRequest: "list_subshell_request",
+    ListSubshellReply: "list_subshell_reply",
+    UsageRequest: "usage_request",
+    UsageReply: "usage_reply",
+
+    // stdin
+    InputRequest: "input_request",
+    InputReply: "input_reply",
+
+    // iopub
+    Stream: "stream",
+    DisplayData: "display_data",
+    UpdateDisplayData: "update_display_data",
+    ExecuteInput: "execute_input",
+    ExecuteResult: "execute_result",
+    Error: "error",
+    Status: "status",
+    ClearOutput: "clear_output",
+    DebugEvent: "debug_event",
+
+    // comms, sent on shell and iopub
+    CommOpen: "comm_open",
+    CommMsg: "comm_msg",
+    CommClose: "comm_close",
+}
+
+impl MessageType {
+    /// The type of the reply to this request, `None` if this is no request.
+    ///
+    /// Unknown requests are answered by a reply named after them.
+    pub fn reply(&self) -> Option<MessageType> {
+        use MessageType as T;
+        Some(match self {
+            T::ExecuteRequest => T::ExecuteReply,
+            T::InspectRequest => T::InspectReply,
+            T::CompleteRequest => T::CompleteReply,
+            T::HistoryRequest => T::HistoryReply,
+            T::IsCompleteRequest => T::IsCompleteReply,
+            T::CommInfoRequest => T::CommInfoReply,
+            T::KernelInfoRequest => T::KernelInfoReply,
+            T::ShutdownRequest => T::ShutdownReply,
+            T::InterruptRequest => T::InterruptReply,
+            T::DebugRequest => T::DebugReply,
+            T::CreateSubshellRequest => T::CreateSubshellReply,
+            T::DeleteSubshellRequest => T::DeleteSubshellReply,
+            T::ListSubshellRequest => T::ListSubshellReply,
+            T::UsageRequest => T::UsageReply,
+            T::InputRequest => T::InputReply,
+            T::Unknown(name) => {
+                let prefix = name.strip_suffix("_request")?;
+                T::Unknown(format!("{prefix}_reply"))
+            }
+            T::ExecuteReply |
+            T::InspectReply |
+            T::CompleteReply |
+            T::HistoryReply |
+            T::IsCompleteReply |
+            T::CommInfoReply |
+            T::KernelInfoReply |
+            T::ShutdownReply |
+            T::InterruptReply |
+            T::DebugReply |
+            T::CreateSubshellReply |
+            T::DeleteSubshellReply |
+            T::ListSubshellReply |
+            T::UsageReply |
+            T::InputReply |
+            T::Stream |
+            T::DisplayData |
+            T::UpdateDisplayData |
+            T::ExecuteInput |
+            T::ExecuteResult |
+            T::Error |
+            T::Status |
+            T::ClearOutput |
+            T::DebugEvent |
+            T::CommOpen |
+            T::CommMsg |
+            T::CommClose => return None,
+        })
+    }
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for MessageType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(MessageType::from(name.as_str()))
+    }
+}
+
+impl IntoValue for MessageType {
+    fn into_value(self, span: Span) -> Value {
+        Value::string(self.as_str(), span)
+    }
+}
+
+impl FromValue for MessageType {
+    fn from_value(v: Value) -> Result<Self, ShellError> {
+        let name = String::from_value(v)?;
+        Ok(MessageType::from(name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_names() {
+        for name in ["execute_request", "comm_msg", "debug_event", "foo_request"] {
+            assert_eq!(MessageType::from(name).as_str(), name);
+        }
+        assert_eq!(MessageType::from("status"), MessageType::Status);
+    }
+
+    #[test]
+    fn requests_have_replies() {
+        assert_eq!(
+            MessageType::KernelInfoRequest.reply(),
+            Some(MessageType::KernelInfoReply)
+        );
+        assert_eq!(
+            MessageType::from("foo_request").reply(),
+            Some(MessageType::Unknown("foo_reply".to_owned()))
+        );
+        assert_eq!(MessageType::CommMsg.reply(), None);
+        assert_eq!(MessageType::ExecuteReply.reply(), None);
+    }
+}
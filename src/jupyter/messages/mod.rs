@@ -1,3 +1,4 @@
+use std::collections::{BTreeSet, VecDeque};
 use std::ops::Deref;
 use std::sync::OnceLock;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -8,24 +9,36 @@ use control::ControlRequest;
 use derive_more::From;
 use hmac::{Hmac, Mac};
 use nu_protocol::{FromValue, IntoValue};
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sha2::Sha256;
+use sha1::Sha1;
 use sha2::digest::InvalidLength;
+use sha2::{Sha256, Sha384, Sha512};
 use uuid::Uuid;
 use zeromq::SocketRecv;
 
+use self::message_type::MessageType;
 use self::shell::ShellRequest;
+use self::stdin::StdinReply;
+use crate::error::MessageError;
+use crate::jupyter::connection_file::SupportedSignatureHashFunction;
 use crate::{CARGO_TOML, Channel};
 
+pub mod comm;
 pub mod control;
+pub mod debug;
 pub mod iopub;
+pub mod message_type;
 pub mod multipart;
 pub mod shell;
+pub mod stdin;
 
 pub static KERNEL_SESSION: KernelSession = KernelSession::new();
 pub static MESSAGE_COUNTER: AtomicUsize = AtomicUsize::new(0);
 pub static DIGESTER: Digester = Digester::new();
+pub static SEEN_SIGNATURES: SeenSignatures = SeenSignatures::new();
 
 pub struct KernelSession(OnceLock<String>);
 
@@ -39,23 +52,125 @@ impl KernelSession {
     }
 }
 
-pub struct Digester(OnceLock<Hmac<Sha256>>);
+pub struct Digester(OnceLock<Signer>);
+
+/// Signs messages according to the signature scheme of the connection file.
+enum Signer {
+    /// An empty key disables signing.
+    Unsigned,
+    Sha1(Hmac<Sha1>),
+    Sha256(Hmac<Sha256>),
+    Sha384(Hmac<Sha384>),
+    Sha512(Hmac<Sha512>),
+}
 
 impl Digester {
     pub const fn new() -> Self {
         Digester(OnceLock::new())
     }
 
-    pub fn key_init(&self, key: &[u8]) -> Result<(), InvalidLength> {
-        self.0.set(Hmac::new_from_slice(key)?).expect("already set");
+    pub fn key_init(
+        &self,
+        key: &[u8],
+        hash_fn: SupportedSignatureHashFunction,
+    ) -> Result<(), InvalidLength> {
+        let signer = match hash_fn {
+            _ if key.is_empty() => Signer::Unsigned,
+            SupportedSignatureHashFunction::Sha1 => Signer::Sha1(Hmac::new_from_slice(key)?),
+            SupportedSignatureHashFunction::Sha256 => Signer::Sha256(Hmac::new_from_slice(key)?),
+            SupportedSignatureHashFunction::Sha384 => Signer::Sha384(Hmac::new_from_slice(key)?),
+            SupportedSignatureHashFunction::Sha512 => Signer::Sha512(Hmac::new_from_slice(key)?),
+        };
+        if self.0.set(signer).is_err() {
+            panic!("already set");
+        }
         Ok(())
     }
 
-    pub fn get(&self) -> &Hmac<Sha256> {
-        match self.0.get() {
-            None => panic!("hmac not initialized"),
-            Some(hmac) => hmac,
+    fn get(&self) -> Result<&Signer, MessageError> {
+        self.0.get().ok_or(MessageError::DigesterUninit)
+    }
+
+    /// Whether messages are signed, an empty key disables signing.
+    pub fn is_signing(&self) -> Result<bool, MessageError> {
+        Ok(!matches!(self.get()?, Signer::Unsigned))
+    }
+
+    /// Sign the header, parent header, metadata and content frames.
+    ///
+    /// Without a key the signature is empty.
+    pub fn sign(&self, frames: [&str; 4]) -> Result<String, MessageError> {
+        fn sign<M: Mac + Clone>(mac: &M, frames: [&str; 4]) -> String {
+            let mut mac = mac.clone();
+            for frame in frames {
+                mac.update(frame.as_bytes());
+            }
+            hex::encode(mac.finalize().into_bytes())
+        }
+
+        Ok(match self.get()? {
+            Signer::Unsigned => String::new(),
+            Signer::Sha1(mac) => sign(mac, frames),
+            Signer::Sha256(mac) => sign(mac, frames),
+            Signer::Sha384(mac) => sign(mac, frames),
+            Signer::Sha512(mac) => sign(mac, frames),
+        })
+    }
+
+    /// Verify the hex encoded signature of the frames in constant time.
+    ///
+    /// Without a key every message is accepted.
+    pub fn verify(&self, signature: &str, frames: [&str; 4]) -> Result<bool, MessageError> {
+        fn verify<M: Mac + Clone>(mac: &M, signature: &[u8], frames: [&str; 4]) -> bool {
+            let mut mac = mac.clone();
+            for frame in frames {
+                mac.update(frame.as_bytes());
+            }
+            mac.verify_slice(signature).is_ok()
+        }
+
+        let signer = self.get()?;
+        let Ok(signature) = hex::decode(signature)
+        else {
+            return Ok(matches!(signer, Signer::Unsigned));
+        };
+        Ok(match signer {
+            Signer::Unsigned => true,
+            Signer::Sha1(mac) => verify(mac, &signature, frames),
+            Signer::Sha256(mac) => verify(mac, &signature, frames),
+            Signer::Sha384(mac) => verify(mac, &signature, frames),
+            Signer::Sha512(mac) => verify(mac, &signature, frames),
+        })
+    }
+}
+
+/// Recently received signatures, used to reject replayed messages.
+///
+/// Only the latest [`SEEN_SIGNATURES_CAPACITY`] signatures are kept, like
+/// jupyter_client does.
+pub struct SeenSignatures(Mutex<(VecDeque<String>, BTreeSet<String>)>);
+
+pub const SEEN_SIGNATURES_CAPACITY: usize = 1 << 16;
+
+impl SeenSignatures {
+    pub const fn new() -> Self {
+        SeenSignatures(Mutex::new((VecDeque::new(), BTreeSet::new())))
+    }
+
+    /// Remember a signature, returns `false` if it was already seen.
+    pub fn insert(&self, signature: &str) -> bool {
+        let mut guard = self.0.lock();
+        let (order, seen) = &mut *guard;
+        if !seen.insert(signature.to_owned()) {
+            return false;
         }
+        order.push_back(signature.to_owned());
+        if order.len() > SEEN_SIGNATURES_CAPACITY &&
+            let Some(oldest) = order.pop_front()
+        {
+            seen.remove(&oldest);
+        }
+        true
     }
 }
 
@@ -65,12 +180,22 @@ pub struct Header {
     pub session: String,
     pub username: String,
     pub date: String,
-    pub msg_type: String, // TODO: make this an enum
+    pub msg_type: MessageType,
     pub version: String,
+    /// Subshell that should handle a shell request, `None` for the main shell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subshell_id: Option<String>,
 }
 
 impl Header {
-    pub fn new(msg_type: impl Into<String>) -> Self {
+    /// Type of the reply to the request with this header.
+    pub fn reply_type(&self) -> Result<MessageType, MessageError> {
+        self.msg_type
+            .reply()
+            .ok_or_else(|| MessageError::NoReply(self.msg_type.clone()))
+    }
+
+    pub fn new(msg_type: MessageType) -> Self {
         let session = KERNEL_SESSION.get();
         let msg_counter = MESSAGE_COUNTER.fetch_add(1, Ordering::SeqCst);
 
@@ -79,13 +204,14 @@ impl Header {
             session: session.to_owned(),
             username: "nu_kernel".to_owned(),
             date: Utc::now().to_rfc3339(),
-            msg_type: msg_type.into(),
+            msg_type,
             version: CARGO_TOML
                 .package
                 .metadata
                 .jupyter
                 .protocol_version
                 .to_owned(),
+            subshell_id: None,
         }
     }
 }
@@ -102,6 +228,7 @@ impl Metadata {
 #[derive(Debug, Deserialize, Clone)]
 pub enum IncomingContent {
     Shell(shell::ShellRequest),
+    Stdin(stdin::StdinReply),
     Control(control::ControlRequest),
 }
 
@@ -109,6 +236,7 @@ pub enum IncomingContent {
 pub enum OutgoingContent {
     Shell(shell::ShellReply),
     Iopub(iopub::IopubBroacast),
+    Stdin(stdin::StdinRequest),
     Control(control::ControlReply),
 }
 
@@ -125,9 +253,13 @@ pub struct Message<C> {
 static ZMQ_WAIT: i32 = 0;
 
 impl Message<IncomingContent> {
-    // TODO: add a real error type here
-    async fn recv<S: SocketRecv>(socket: &mut S, source: Channel) -> Result<Self, ()> {
-        let mut zmq_message = socket.recv().await.unwrap().into_vec().into_iter();
+    async fn recv<S: SocketRecv>(socket: &mut S, source: Channel) -> Result<Self, MessageError> {
+        let mut zmq_message = socket
+            .recv()
+            .await
+            .map_err(MessageError::Recv)?
+            .into_vec()
+            .into_iter();
         let zmq_message = &mut zmq_message;
 
         let mut zmq_identities = Vec::new();
@@ -138,53 +270,88 @@ impl Message<IncomingContent> {
             zmq_identities.push(bytes.to_owned());
         }
 
-        // TODO: add error handling for this here
-        fn next_string(byte_iter: &mut impl Iterator<Item = Bytes>) -> String {
-            String::from_utf8(byte_iter.next().unwrap().to_vec()).unwrap()
+        fn next_string(
+            byte_iter: &mut impl Iterator<Item = Bytes>,
+            frame: &'static str,
+        ) -> Result<String, MessageError> {
+            let bytes = byte_iter.next().ok_or(MessageError::MissingFrame(frame))?;
+            String::from_utf8(bytes.to_vec()).map_err(|source| MessageError::Utf8 { frame, source })
+        }
+
+        fn parse<T: DeserializeOwned>(s: &str, frame: &'static str) -> Result<T, MessageError> {
+            serde_json::from_str(s).map_err(|source| MessageError::Parse { frame, source })
         }
 
-        let signature = next_string(zmq_message);
+        let signature = next_string(zmq_message, "signature")?;
+        let header = next_string(zmq_message, "header")?;
+        let parent_header = next_string(zmq_message, "parent_header")?;
+        let metadata = next_string(zmq_message, "metadata")?;
+        let content = next_string(zmq_message, "content")?;
 
-        let header = next_string(zmq_message);
-        let header: Header = serde_json::from_str(&header).unwrap();
+        // only trust messages signed with our key, anyone else might reach the ports
+        if !DIGESTER.verify(&signature, [&header, &parent_header, &metadata, &content])? {
+            return Err(MessageError::InvalidSignature);
+        }
+        // unsigned messages all share the empty signature
+        if DIGESTER.is_signing()? && !SEEN_SIGNATURES.insert(&signature) {
+            return Err(MessageError::Replayed);
+        }
 
-        let parent_header = next_string(zmq_message);
+        let header: Header = parse(&header, "header")?;
         let parent_header: Option<Header> = match parent_header.as_str() {
             "{}" => None,
-            ph => serde_json::from_str(ph).unwrap(),
+            ph => parse(ph, "parent_header")?,
+        };
+        let metadata: Metadata = parse(&metadata, "metadata")?;
+        let buffers: Vec<Bytes> = zmq_message.collect();
+        let message = Message {
+            zmq_identities,
+            header,
+            parent_header,
+            metadata,
+            content: (),
+            buffers,
         };
 
-        let metadata = next_string(zmq_message);
-        let metadata: Metadata = serde_json::from_str(&metadata).unwrap();
-
-        let content = next_string(zmq_message);
         // FIXME: this is a annoying solution, should be handled somehow by the type
         // system better
-        let content = match source {
-            Channel::Shell => {
-                IncomingContent::Shell(ShellRequest::parse_variant(&header.msg_type, &content)?)
-            }
-            Channel::Stdin => todo!(),
-            Channel::Control => {
-                IncomingContent::Control(ControlRequest::parse_variant(&header.msg_type, &content)?)
+        let msg_type = &message.header.msg_type;
+        let parsed = match source {
+            Channel::Shell => ShellRequest::parse_variant(msg_type, &content)
+                .map(|c| c.map(IncomingContent::Shell)),
+            Channel::Stdin => {
+                StdinReply::parse_variant(msg_type, &content).map(|c| c.map(IncomingContent::Stdin))
             }
+            Channel::Control => ControlRequest::parse_variant(msg_type, &content)
+                .map(|c| c.map(IncomingContent::Control)),
         };
+        match parsed {
+            Ok(Some(content)) => Ok(message.with_content(content)),
+            Ok(None) => Err(MessageError::UnknownRequest(Box::new(message))),
+            Err(source) => Err(MessageError::InvalidRequest {
+                request: Box::new(message),
+                source,
+            }),
+        }
+    }
+}
 
-        let buffers: Vec<Bytes> = zmq_message.collect();
-
-        Ok(Message {
-            zmq_identities,
-            header,
-            parent_header,
-            metadata,
+impl<C> Message<C> {
+    /// Replace the content, keeping routing and headers.
+    pub fn with_content<T>(self, content: T) -> Message<T> {
+        Message {
+            zmq_identities: self.zmq_identities,
+            header: self.header,
+            parent_header: self.parent_header,
+            metadata: self.metadata,
             content,
-            buffers,
-        })
+            buffers: self.buffers,
+        }
     }
 }
 
 impl Message<ShellRequest> {
-    pub async fn recv<S: SocketRecv>(socket: &mut S) -> Result<Self, ()> {
+    pub async fn recv<S: SocketRecv>(socket: &mut S) -> Result<Self, MessageError> {
         let msg = Message::<IncomingContent>::recv(socket, Channel::Shell).await?;
         let Message {
             zmq_identities,
@@ -194,23 +361,24 @@ impl Message<ShellRequest> {
             content,
             buffers,
         } = msg;
-        let IncomingContent::Shell(content) = content
-        else {
-            panic!("unexpected content");
-        };
-        Ok(Message {
+        let message = Message {
             zmq_identities,
             header,
             parent_header,
             metadata,
-            content,
+            content: (),
             buffers,
-        })
+        };
+        let IncomingContent::Shell(content) = content
+        else {
+            return Err(MessageError::UnknownRequest(Box::new(message)));
+        };
+        Ok(message.with_content(content))
     }
 }
 
 impl Message<ControlRequest> {
-    pub async fn recv<S: SocketRecv>(socket: &mut S) -> Result<Self, ()> {
+    pub async fn recv<S: SocketRecv>(socket: &mut S) -> Result<Self, MessageError> {
         let msg = Message::<IncomingContent>::recv(socket, Channel::Control).await?;
         let Message {
             zmq_identities,
@@ -220,17 +388,89 @@ impl Message<ControlRequest> {
             content,
             buffers,
         } = msg;
+        let message = Message {
+            zmq_identities,
+            header,
+            parent_header,
+            metadata,
+            content: (),
+            buffers,
+        };
         let IncomingContent::Control(content) = content
         else {
-            panic!("unexpected content");
+            return Err(MessageError::UnknownRequest(Box::new(message)));
         };
-        Ok(Message {
+        Ok(message.with_content(content))
+    }
+}
+
+impl Message<StdinReply> {
+    pub async fn recv<S: SocketRecv>(socket: &mut S) -> Result<Self, MessageError> {
+        let msg = Message::<IncomingContent>::recv(socket, Channel::Stdin).await?;
+        let Message {
             zmq_identities,
             header,
             parent_header,
             metadata,
             content,
             buffers,
-        })
+        } = msg;
+        let message = Message {
+            zmq_identities,
+            header,
+            parent_header,
+            metadata,
+            content: (),
+            buffers,
+        };
+        let IncomingContent::Stdin(content) = content
+        else {
+            return Err(MessageError::UnknownRequest(Box::new(message)));
+        };
+        Ok(message.with_content(content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_signatures() {
+        let digester = Digester::new();
+        digester
+            .key_init(b"secret", SupportedSignatureHashFunction::Sha256)
+            .unwrap();
+        let frames = ["{\"msg_id\":\"1\"}", "{}", "{}", "{\"code\":\"ls\"}"];
+
+        let signature = digester.sign(frames).unwrap();
+        assert!(digester.verify(&signature, frames).unwrap());
+        assert!(
+            !digester
+                .verify(&signature, ["{}", "{}", "{}", "{}"])
+                .unwrap()
+        );
+        assert!(!digester.verify("not hex", frames).unwrap());
+        assert!(!digester.verify("", frames).unwrap());
+
+        let sha512 = Digester::new();
+        sha512
+            .key_init(b"secret", SupportedSignatureHashFunction::Sha512)
+            .unwrap();
+        let sha512_signature = sha512.sign(frames).unwrap();
+        assert_eq!(sha512_signature.len(), 128);
+        assert!(sha512.verify(&sha512_signature, frames).unwrap());
+        assert!(!sha512.verify(&signature, frames).unwrap());
+
+        let unsigned = Digester::new();
+        unsigned
+            .key_init(b"", SupportedSignatureHashFunction::Sha256)
+            .unwrap();
+        assert_eq!(unsigned.sign(frames).unwrap(), "");
+        assert!(unsigned.verify("", frames).unwrap());
+
+        let seen = SeenSignatures::new();
+        assert!(seen.insert(&signature));
+        assert!(!seen.insert(&signature));
     }
 }
@@ -1,43 +1,42 @@
 use std::iter;
 
 use bytes::Bytes;
-use hmac::Mac;
+use serde::Serialize;
 use zeromq::{SocketSend, ZmqMessage};
 
 use super::{DIGESTER, Message, OutgoingContent};
+use crate::error::MessageError;
 
 pub struct Multipart(ZmqMessage);
 
 impl Multipart {
-    pub async fn send<S: SocketSend>(self, socket: &mut S) -> Result<(), ()> {
-        socket.send(self.0).await.unwrap();
-        Ok(())
+    pub async fn send<S: SocketSend>(self, socket: &mut S) -> Result<(), MessageError> {
+        socket.send(self.0).await.map_err(MessageError::Send)
     }
 }
 
+fn to_json(value: &impl Serialize) -> Result<String, MessageError> {
+    serde_json::to_string(value).map_err(MessageError::Serialize)
+}
+
 impl Message<OutgoingContent> {
-    fn into_multipart_impl(self) -> Result<Multipart, ()> {
+    fn into_multipart_impl(self) -> Result<Multipart, MessageError> {
         let zmq_identities = self.zmq_identities;
-        let header = serde_json::to_string(&self.header).unwrap();
+        let header = to_json(&self.header)?;
         let parent_header = match self.parent_header {
-            Some(ref parent_header) => serde_json::to_string(parent_header).unwrap(),
+            Some(ref parent_header) => to_json(parent_header)?,
             None => "{}".to_owned(),
         };
-        let metadata = serde_json::to_string(&self.metadata).unwrap();
+        let metadata = to_json(&self.metadata)?;
         let content = match self.content {
-            OutgoingContent::Shell(ref content) => serde_json::to_string(content).unwrap(),
-            OutgoingContent::Iopub(ref content) => serde_json::to_string(content).unwrap(),
-            OutgoingContent::Control(ref content) => serde_json::to_string(content).unwrap(),
+            OutgoingContent::Shell(ref content) => to_json(content)?,
+            OutgoingContent::Iopub(ref content) => to_json(content)?,
+            OutgoingContent::Stdin(ref content) => to_json(content)?,
+            OutgoingContent::Control(ref content) => to_json(content)?,
         };
         let buffers = self.buffers;
 
-        let mut digester = DIGESTER.get().clone();
-        digester.update(header.as_bytes());
-        digester.update(parent_header.as_bytes());
-        digester.update(metadata.as_bytes());
-        digester.update(content.as_bytes());
-        let signature = digester.finalize().into_bytes();
-        let signature = hex::encode(signature);
+        let signature = DIGESTER.sign([&header, &parent_header, &metadata, &content])?;
 
         let frames: Vec<Bytes> = zmq_identities
             .into_iter()
@@ -50,7 +49,9 @@ impl Message<OutgoingContent> {
             .chain(buffers)
             .collect();
 
-        Ok(Multipart(ZmqMessage::try_from(frames).unwrap()))
+        ZmqMessage::try_from(frames)
+            .map(Multipart)
+            .map_err(|_| MessageError::EmptyMultipart)
     }
 }
 
@@ -58,7 +59,7 @@ impl<C> Message<C>
 where
     C: Into<OutgoingContent>,
 {
-    pub fn into_multipart(self) -> Result<Multipart, ()> {
+    pub fn into_multipart(self) -> Result<Multipart, MessageError> {
         let Message {
             zmq_identities,
             header,
@@ -1,25 +1,46 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::jupyter::kernel_info::KernelInfo;
+use crate::jupyter::messages::comm::{
+    CommClose, CommInfoReply, CommInfoRequest, CommMsg, CommOpen,
+};
+use crate::jupyter::messages::message_type::MessageType;
 
 #[derive(Debug, Deserialize, Clone)]
 pub enum ShellRequest {
     Execute(ExecuteRequest),
     IsComplete(IsCompleteRequest),
     KernelInfo,
+    Complete(CompleteRequest),
+    Inspect(InspectRequest),
+    CommInfo(CommInfoRequest),
+    CommOpen(CommOpen),
+    CommMsg(CommMsg),
+    CommClose(CommClose),
+    History(HistoryRequest),
 }
 
 impl ShellRequest {
-    pub fn parse_variant(variant: &str, body: &str) -> Result<Self, ()> {
-        match variant {
-            "execute_request" => Ok(Self::Execute(serde_json::from_str(body).unwrap())),
-            "is_complete_request" => Ok(Self::IsComplete(serde_json::from_str(body).unwrap())),
-            "kernel_info_request" => Ok(Self::KernelInfo),
-            _ => {
-                eprintln!("unknown request {variant}");
-                Err(())
-            }
-        }
+    /// Parse the content of a request, `None` if the request type is unknown.
+    pub fn parse_variant(
+        variant: &MessageType,
+        body: &str,
+    ) -> Result<Option<Self>, serde_json::Error> {
+        Ok(Some(match variant {
+            MessageType::ExecuteRequest => Self::Execute(serde_json::from_str(body)?),
+            MessageType::IsCompleteRequest => Self::IsComplete(serde_json::from_str(body)?),
+            MessageType::KernelInfoRequest => Self::KernelInfo,
+            MessageType::CompleteRequest => Self::Complete(serde_json::from_str(body)?),
+            MessageType::InspectRequest => Self::Inspect(serde_json::from_str(body)?),
+            MessageType::CommInfoRequest => Self::CommInfo(serde_json::from_str(body)?),
+            MessageType::CommOpen => Self::CommOpen(serde_json::from_str(body)?),
+            MessageType::CommMsg => Self::CommMsg(serde_json::from_str(body)?),
+            MessageType::CommClose => Self::CommClose(serde_json::from_str(body)?),
+            MessageType::HistoryRequest => Self::History(serde_json::from_str(body)?),
+            _ => return Ok(None),
+        }))
     }
 }
 
@@ -36,17 +57,11 @@ pub enum ShellReply {
         #[serde(skip_serializing_if = "Option::is_none")]
         execution_count: Option<usize>,
     },
-}
-
-impl ShellReply {
-    pub fn msg_type(request_type: &'_ str) -> Result<&'static str, ()> {
-        Ok(match request_type {
-            "kernel_info_request" => "kernel_info_reply",
-            "execute_request" => "execute_reply",
-            "is_complete_request" => "is_complete_reply",
-            _ => todo!("handle unknown requests"),
-        })
-    }
+    /// Request was not handled as a previous execution failed.
+    Aborted {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        execution_count: Option<usize>,
+    },
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -55,6 +70,10 @@ pub enum ShellReplyOk {
     KernelInfo(KernelInfo),
     Execute(ExecuteReply),
     IsComplete(IsCompleteReply),
+    Complete(CompleteReply),
+    Inspect(InspectReply),
+    CommInfo(CommInfoReply),
+    History(HistoryReply),
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -64,8 +83,8 @@ pub struct ExecuteRequest {
     pub silent: bool,
     // TODO: check if this assertion can still be unhold or should be
     pub store_history: bool,
-    // TODO: figure out what to do with this
-    pub user_expressions: serde_json::Value,
+    #[serde(default)]
+    pub user_expressions: HashMap<String, String>,
     pub allow_stdin: bool,
     pub stop_on_error: bool,
 }
@@ -73,7 +92,22 @@ pub struct ExecuteRequest {
 #[derive(Debug, Serialize, Clone)]
 pub struct ExecuteReply {
     pub execution_count: usize,
-    pub user_expressions: serde_json::Value,
+    pub user_expressions: HashMap<String, UserExpression>,
+}
+
+/// Result of a single user expression, evaluated after the cell.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UserExpression {
+    Ok {
+        data: HashMap<String, serde_json::Value>,
+        metadata: HashMap<String, serde_json::Value>,
+    },
+    Error {
+        ename: String,
+        evalue: String,
+        traceback: Vec<String>,
+    },
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -89,3 +123,95 @@ pub enum IsCompleteReply {
     Invalid,
     Unknown,
 }
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CompleteRequest {
+    pub code: String,
+    pub cursor_pos: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CompleteReply {
+    pub matches: Vec<String>,
+    pub cursor_start: usize,
+    pub cursor_end: usize,
+    pub metadata: CompleteReplyMetadata,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CompleteReplyMetadata {
+    /// Typed matches, used by JupyterLab to show icons and signatures.
+    #[serde(rename = "_jupyter_types_experimental")]
+    pub types: Vec<CompleteReplyType>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CompleteReplyType {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct InspectRequest {
+    pub code: String,
+    pub cursor_pos: usize,
+    #[serde(default)]
+    pub detail_level: u8,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct InspectReply {
+    pub found: bool,
+    pub data: HashMap<String, String>,
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HistoryRequest {
+    #[serde(default)]
+    pub output: bool,
+    #[serde(default)]
+    pub raw: bool,
+    #[serde(flatten)]
+    pub access: HistoryAccess,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "hist_access_type", rename_all = "snake_case")]
+pub enum HistoryAccess {
+    Range {
+        #[serde(default)]
+        session: i64,
+        #[serde(default)]
+        start: usize,
+        stop: Option<usize>,
+    },
+    Tail {
+        n: usize,
+    },
+    Search {
+        pattern: String,
+        #[serde(default)]
+        unique: bool,
+        n: Option<usize>,
+    },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HistoryReply {
+    pub history: Vec<HistoryItem>,
+}
+
+/// Entry of the history, serialized as tuple.
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum HistoryItem {
+    /// `(session, line, input)`
+    Input(u32, usize, String),
+    /// `(session, line, (input, output))`, used if the request asked for output
+    InputOutput(u32, usize, (String, Option<String>)),
+}
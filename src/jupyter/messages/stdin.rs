@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+use crate::jupyter::messages::message_type::MessageType;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum StdinRequest {
+    Input(InputRequest),
+}
+
+impl StdinRequest {
+    pub fn msg_type(&self) -> MessageType {
+        match self {
+            StdinRequest::Input(_) => MessageType::InputRequest,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct InputRequest {
+    pub prompt: String,
+    /// Whether the frontend should hide the typed input, e.g. for passwords.
+    pub password: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub enum StdinReply {
+    Input(InputReply),
+}
+
+impl StdinReply {
+    /// Parse the content of a reply, `None` if the reply type is unknown.
+    pub fn parse_variant(
+        variant: &MessageType,
+        body: &str,
+    ) -> Result<Option<Self>, serde_json::Error> {
+        Ok(Some(match variant {
+            MessageType::InputReply => Self::Input(serde_json::from_str(body)?),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct InputReply {
+    pub value: String,
+}
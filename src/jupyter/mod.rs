@@ -4,6 +4,7 @@ pub mod connection_file;
 pub mod kernel_info;
 pub mod messages;
 pub mod register_kernel;
+pub mod usage;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Shutdown {
@@ -0,0 +1,149 @@
+//! Resource usage of the kernel, replied to `usage_request` in the format of
+//! `jupyter-resource-usage`.
+
+use std::thread;
+
+use nu_protocol::{IntoValue, Span, Value};
+use parking_lot::Mutex;
+use serde::Serialize;
+use sysinfo::{MINIMUM_CPU_UPDATE_INTERVAL, Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+/// CPU usage is measured between two refreshes, so the system is kept
+/// between measurements.
+static SYSTEM: Mutex<Option<System>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Clone, IntoValue)]
+pub struct ResourceUsage {
+    pub hostname: String,
+    pub pid: u32,
+    /// CPU usage of the kernel and its child processes in percent of a single
+    /// core.
+    pub kernel_cpu: f32,
+    /// Resident memory of the kernel and its child processes.
+    pub kernel_memory: Memory,
+    /// `None` if threads cannot be counted on this platform.
+    pub kernel_threads: Option<u32>,
+    pub host_cpu_percent: f32,
+    pub cpu_count: u32,
+    pub host_virtual_memory: VirtualMemory,
+    /// Child processes of the kernel, e.g. external commands.
+    pub children: Vec<ChildProcess>,
+}
+
+#[derive(Debug, Serialize, Clone, IntoValue)]
+pub struct VirtualMemory {
+    pub total: Memory,
+    pub available: Memory,
+    pub used: Memory,
+    pub free: Memory,
+    pub percent: f64,
+}
+
+#[derive(Debug, Serialize, Clone, IntoValue)]
+pub struct ChildProcess {
+    pub pid: u32,
+    pub name: String,
+    pub cpu: f32,
+    pub memory: Memory,
+}
+
+/// Amount of bytes, serialized as a number and converted into a filesize in
+/// nu.
+#[derive(Debug, Serialize, Clone, Copy)]
+#[serde(transparent)]
+pub struct Memory(pub u64);
+
+impl IntoValue for Memory {
+    fn into_value(self, span: Span) -> Value {
+        Value::filesize(self.0.try_into().unwrap_or(i64::MAX), span)
+    }
+}
+
+impl ResourceUsage {
+    /// Measure the current resource usage.
+    ///
+    /// The first measurement blocks for a moment to get a CPU usage.
+    pub fn measure() -> Self {
+        let mut system = SYSTEM.lock();
+        let system = match system.as_mut() {
+            Some(system) => system,
+            None => {
+                let mut new_system = System::new();
+                refresh(&mut new_system);
+                thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+                system.insert(new_system)
+            }
+        };
+        refresh(system);
+
+        let pid = Pid::from_u32(std::process::id());
+        let kernel = system.process(pid);
+        let children: Vec<_> = system
+            .processes()
+            .values()
+            // threads are listed as processes on linux
+            .filter(|process| process.thread_kind().is_none())
+            .filter(|process| is_descendant(system, process.pid(), pid))
+            .map(|process| ChildProcess {
+                pid: process.pid().as_u32(),
+                name: process.name().to_string_lossy().into_owned(),
+                cpu: process.cpu_usage(),
+                memory: Memory(process.memory()),
+            })
+            .collect();
+
+        let total = system.total_memory();
+        let available = system.available_memory();
+        ResourceUsage {
+            hostname: System::host_name().unwrap_or_default(),
+            pid: pid.as_u32(),
+            kernel_cpu: kernel.map(|kernel| kernel.cpu_usage()).unwrap_or_default() +
+                children.iter().map(|child| child.cpu).sum::<f32>(),
+            kernel_memory: Memory(
+                kernel.map(|kernel| kernel.memory()).unwrap_or_default() +
+                    children.iter().map(|child| child.memory.0).sum::<u64>(),
+            ),
+            kernel_threads: kernel
+                .and_then(|kernel| kernel.tasks())
+                .map(|tasks| tasks.len() as u32),
+            host_cpu_percent: system.global_cpu_usage(),
+            cpu_count: system.cpus().len() as u32,
+            host_virtual_memory: VirtualMemory {
+                total: Memory(total),
+                available: Memory(available),
+                used: Memory(system.used_memory()),
+                free: Memory(system.free_memory()),
+                percent: match total {
+                    0 => 0.0,
+                    _ => (total - available) as f64 / total as f64 * 100.0,
+                },
+            },
+            children,
+        }
+    }
+}
+
+fn refresh(system: &mut System) {
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing()
+            .with_cpu()
+            .with_memory()
+            .with_tasks(),
+    );
+}
+
+/// Whether `pid` is a (grand)child of `ancestor`.
+pub(crate) fn is_descendant(system: &System, pid: Pid, ancestor: Pid) -> bool {
+    let mut parent = system.process(pid).and_then(|process| process.parent());
+    while let Some(pid) = parent {
+        if pid == ancestor {
+            return true;
+        }
+        parent = system.process(pid).and_then(|process| process.parent());
+    }
+    false
+}
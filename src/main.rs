@@ -3,20 +3,29 @@
 #![allow(unused_variables)]
 #![allow(clippy::result_large_err)]
 
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::{panic, process};
 
 use clap::{Parser, Subcommand};
 use const_format::formatcp;
 use handlers::shell::Cell;
 use handlers::stream::StreamHandler;
+use handlers::subshell::Subshells;
+use history::History;
 use jupyter::connection_file::ConnectionFile;
 use jupyter::messages::iopub;
 use jupyter::register_kernel::{RegisterLocation, register_kernel};
+use nu::commands::comm::SharedCommRegistry;
 use nu::commands::{JupyterCommandContext, add_jupyter_command_context};
+use nu::debugger::DebugSession;
 use nu::konst::Konst;
 use nu::render::FormatDeclIds;
+use nu::render::registry::SharedRendererRegistry;
+use nu::settings::SharedSettings;
 use nu_protocol::engine::Stack;
+use parking_lot::Mutex;
 use tokio::sync::{broadcast, mpsc};
 use zeromq::{PubSocket, RepSocket, RouterSocket, Socket, ZmqResult};
 
@@ -24,6 +33,7 @@ use crate::jupyter::messages::DIGESTER;
 
 mod error;
 mod handlers;
+mod history;
 mod jupyter;
 mod nu;
 mod util;
@@ -75,12 +85,7 @@ struct Sockets {
 
 impl Sockets {
     async fn start(connection_file: &ConnectionFile) -> ZmqResult<Self> {
-        let endpoint = |port| {
-            format!(
-                "{}://{}:{}",
-                connection_file.transport, connection_file.ip, port
-            )
-        };
+        let endpoint = |port| connection_file.endpoint(port);
 
         let mut shell = ShellSocket::new();
         shell.bind(&endpoint(&connection_file.shell_port)).await?;
@@ -137,7 +142,12 @@ async fn start_kernel(connection_file_path: impl AsRef<Path>) {
 
     let connection_file = ConnectionFile::from_path(connection_file_path).unwrap();
     let sockets = Sockets::start(&connection_file).await.unwrap();
-    DIGESTER.key_init(&connection_file.key).unwrap();
+    DIGESTER
+        .key_init(
+            &connection_file.key,
+            connection_file.signature_scheme.hash_fn,
+        )
+        .unwrap();
 
     let mut engine_state = nu::initial_engine_state();
     let format_decl_ids = FormatDeclIds::find(&engine_state).unwrap();
@@ -147,10 +157,30 @@ async fn start_kernel(connection_file_path: impl AsRef<Path>) {
     let (engine_state, interrupt_signal) = nu::add_interrupt_signal(engine_state);
 
     let (iopub_tx, iopub_rx) = mpsc::channel(1);
+    let (stdin_tx, stdin_rx) = mpsc::channel(1);
+    let (shell_tx, shell_rx) = mpsc::channel(1);
+    let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+    let (base_requests_tx, base_requests_rx) = mpsc::unbounded_channel();
     let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
 
+    let comms = SharedCommRegistry::default();
+    let renderers = SharedRendererRegistry::default();
+    let settings = SharedSettings::default();
+    let display_ids = Arc::new(Mutex::new(HashSet::new()));
+    let debugger = Arc::new(DebugSession::new(iopub_tx.clone()));
+    let subshells = Arc::new(Subshells::new(
+        requests_tx,
+        base_requests_tx,
+        shutdown_tx.clone(),
+    ));
+
     let ctx = JupyterCommandContext {
         iopub: iopub_tx.clone(),
+        stdin: stdin_tx,
+        comms: comms.clone(),
+        renderers: renderers.clone(),
+        settings: settings.clone(),
+        display_ids: display_ids.clone(),
         format_decl_ids,
         konst,
         spans: spans.clone(),
@@ -165,7 +195,7 @@ async fn start_kernel(connection_file_path: impl AsRef<Path>) {
         .stdout_file(stdout_file)
         .stderr_file(stderr_file);
 
-    let cell = Cell::new();
+    let cell = Arc::new(Mutex::new(Cell::new()));
 
     let heartbeat_task = tokio::spawn(handlers::heartbeat::handle(
         sockets.heartbeat,
@@ -178,8 +208,14 @@ async fn start_kernel(connection_file_path: impl AsRef<Path>) {
         iopub_rx,
     ));
 
+    let stdin_task = tokio::spawn(handlers::stdin::handle(
+        sockets.stdin,
+        shutdown_rx.resubscribe(),
+        stdin_rx,
+    ));
+
     let shell_ctx = handlers::shell::HandlerContext {
-        socket: sockets.shell,
+        shell: shell_tx,
         iopub: iopub_tx,
         stdout_handler,
         stderr_handler,
@@ -189,21 +225,43 @@ async fn start_kernel(connection_file_path: impl AsRef<Path>) {
         spans,
         stack,
         cell,
+        comms,
+        renderers,
+        settings,
+        display_ids,
+        history: Arc::new(Mutex::new(History::load())),
+        debugger: debugger.clone(),
+        subshell_id: None,
+        subshells: subshells.clone(),
     };
     let shell_task = tokio::spawn(handlers::shell::handle(
         shell_ctx,
         shutdown_rx.resubscribe(),
+        requests_rx,
+        Some(base_requests_rx),
+    ));
+
+    // the last handler takes the receiver, the shutdown waits for every receiver
+    let router_task = tokio::spawn(handlers::subshell::route(
+        sockets.shell,
+        shutdown_rx,
+        subshells.clone(),
+        shell_rx,
     ));
 
     let control_task = tokio::spawn(handlers::control::handle(
         sockets.control,
         shutdown_tx,
         interrupt_signal,
+        debugger,
+        subshells,
     ));
 
     heartbeat_task.await.unwrap();
     iopub_task.await.unwrap();
+    stdin_task.await.unwrap();
     shell_task.await.unwrap();
+    router_task.await.unwrap();
     control_task.await.unwrap();
 }
 
@@ -0,0 +1,76 @@
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{Example, PipelineData, ShellError, Signature, Type};
+
+use super::{COMMANDS_TOML, JupyterCommandContext};
+use crate::jupyter::messages::iopub::{ClearOutput, IopubBroacast};
+
+#[derive(Debug, Clone)]
+pub struct Clear(JupyterCommandContext);
+
+impl Clear {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Clear {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.clear.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.clear.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.clear.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.clear.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .switch(
+                "wait",
+                "Wait to clear the output until new output is available",
+                Some('w'),
+            )
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .category(super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .clear
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let wait = call.has_flag(engine_state, stack, "wait")?;
+
+        let konst = self.0.konst.data(stack, call.head)?;
+        if konst.execute.silent {
+            return Ok(PipelineData::Empty);
+        }
+        let broadcast = IopubBroacast::ClearOutput(ClearOutput { wait });
+        self.0.publish(stack, call.head, broadcast)?;
+
+        Ok(PipelineData::Empty)
+    }
+}
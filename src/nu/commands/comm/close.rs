@@ -0,0 +1,84 @@
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{
+    Example, PipelineData, ShellError, Signature, Spanned, SyntaxShape, Type, Value,
+};
+
+use super::super::{COMMANDS_TOML, JupyterCommandContext};
+use crate::jupyter::messages::comm::CommClose;
+use crate::jupyter::messages::iopub::IopubBroacast;
+
+#[derive(Debug, Clone)]
+pub struct Close(JupyterCommandContext);
+
+impl Close {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Close {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.comm_close.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.comm_close.description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.comm_close.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("comm_id", SyntaxShape::String, "Id of an open comm")
+            .optional("data", SyntaxShape::Any, "Data to send when closing")
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .category(super::super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .comm_close
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let comm_id: Spanned<String> = call.req(engine_state, stack, 0)?;
+        let data: Option<Value> = call.opt(engine_state, stack, 1)?;
+        let data = match data {
+            Some(data) => self.0.to_json(engine_state, stack, &data)?,
+            None => serde_json::Value::Object(Default::default()),
+        };
+
+        if self.0.comms.lock().close(&comm_id.item).is_none() {
+            return Err(ShellError::IncorrectValue {
+                msg: "no open comm with this id".to_owned(),
+                val_span: comm_id.span,
+                call_span: call.head,
+            });
+        }
+
+        let broadcast = IopubBroacast::CommClose(CommClose {
+            comm_id: comm_id.item,
+            data,
+        });
+        self.0.publish(stack, call.head, broadcast)?;
+
+        Ok(PipelineData::Empty)
+    }
+}
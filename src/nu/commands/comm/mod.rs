@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use nu_engine::get_full_help;
+use nu_protocol::engine::{Call, Closure, Command, EngineState, Stack};
+use nu_protocol::{IntoPipelineData, IntoValue, PipelineData, ShellError, Signature, Type, Value};
+use parking_lot::Mutex;
+
+use super::COMMANDS_TOML;
+use crate::jupyter::messages::comm::CommInfo;
+
+pub mod close;
+pub mod on_msg;
+pub mod open;
+pub mod send;
+
+pub type SharedCommRegistry = Arc<Mutex<CommRegistry>>;
+
+/// Open comms and the closures handling their messages.
+///
+/// Handlers are keyed by either a comm id or a target name.
+/// As comm ids are UUIDs, they don't collide with sensible target names.
+#[derive(Debug, Default)]
+pub struct CommRegistry {
+    comms: HashMap<String, CommInfo>,
+    handlers: HashMap<String, CommHandler>,
+}
+
+/// Closure registered via `nuju comm on-msg`.
+#[derive(Debug, Clone)]
+pub struct CommHandler {
+    pub closure: Closure,
+    /// Shell that registered the handler, `None` for the main shell.
+    ///
+    /// Closures only exist in the engine state of their shell, so they have
+    /// to run there.
+    pub subshell_id: Option<String>,
+}
+
+impl CommRegistry {
+    pub fn open(&mut self, comm_id: String, target_name: String) {
+        self.comms.insert(comm_id, CommInfo { target_name });
+    }
+
+    /// Close a comm and drop the handler registered for its comm id.
+    pub fn close(&mut self, comm_id: &str) -> Option<CommInfo> {
+        self.handlers.remove(comm_id);
+        self.comms.remove(comm_id)
+    }
+
+    pub fn is_open(&self, comm_id: &str) -> bool {
+        self.comms.contains_key(comm_id)
+    }
+
+    /// All open comms, optionally filtered by target name.
+    pub fn comms(&self, target_name: Option<&str>) -> HashMap<String, CommInfo> {
+        self.comms
+            .iter()
+            .filter(|(_, info)| target_name.is_none_or(|name| info.target_name == name))
+            .map(|(id, info)| (id.clone(), info.clone()))
+            .collect()
+    }
+
+    pub fn set_handler(&mut self, comm_id_or_target: String, handler: CommHandler) {
+        self.handlers.insert(comm_id_or_target, handler);
+    }
+
+    /// Drop the handlers of a deleted subshell.
+    pub fn remove_subshell(&mut self, subshell_id: &str) {
+        self.handlers
+            .retain(|_, handler| handler.subshell_id.as_deref() != Some(subshell_id));
+    }
+
+    /// Whether the frontend may open comms for this target.
+    pub fn accepts_target(&self, target_name: &str) -> bool {
+        self.handlers.contains_key(target_name)
+    }
+
+    /// Find the handler for a comm, handlers for the comm id take precedence
+    /// over handlers for the target name.
+    pub fn handler(&self, comm_id: &str) -> Option<(CommHandler, String)> {
+        let info = self.comms.get(comm_id)?;
+        let handler = self
+            .handlers
+            .get(comm_id)
+            .or_else(|| self.handlers.get(&info.target_name))?;
+        Some((handler.clone(), info.target_name.clone()))
+    }
+}
+
+/// Message passed to the closures registered via `nuju comm on-msg`.
+#[derive(Debug, Clone, IntoValue)]
+pub struct CommMessage {
+    pub comm_id: String,
+    pub target_name: String,
+    pub data: Value,
+}
+
+#[derive(Clone)]
+pub struct Comm;
+
+impl Command for Comm {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.comm.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.comm.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.comm.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.comm.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(Self.name())
+            .category(super::category())
+            .input_output_types(vec![(Type::Nothing, Type::String)])
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        Ok(
+            Value::string(get_full_help(&Comm, engine_state, stack), call.head)
+                .into_pipeline_data(),
+        )
+    }
+}
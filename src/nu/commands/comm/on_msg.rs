@@ -0,0 +1,80 @@
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Closure, Command, EngineState, Stack};
+use nu_protocol::{Example, PipelineData, ShellError, Signature, SyntaxShape, Type};
+
+use super::super::{COMMANDS_TOML, JupyterCommandContext};
+use super::CommHandler;
+
+#[derive(Debug, Clone)]
+pub struct OnMsg(JupyterCommandContext);
+
+impl OnMsg {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for OnMsg {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.comm_on_msg.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.comm_on_msg.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.comm_on_msg.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.comm_on_msg.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required(
+                "comm_id_or_target",
+                SyntaxShape::String,
+                "Comm id or target name to handle messages for",
+            )
+            .required(
+                "handler",
+                SyntaxShape::Closure(Some(vec![SyntaxShape::Any])),
+                "Closure called with every received message",
+            )
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .category(super::super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .comm_on_msg
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let comm_id_or_target: String = call.req(engine_state, stack, 0)?;
+        let closure: Closure = call.req(engine_state, stack, 1)?;
+        let konst = self.0.konst.data(stack, call.head)?;
+        let handler = CommHandler {
+            closure,
+            subshell_id: konst.subshell_id,
+        };
+        self.0.comms.lock().set_handler(comm_id_or_target, handler);
+        Ok(PipelineData::Empty)
+    }
+}
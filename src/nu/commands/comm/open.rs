@@ -0,0 +1,88 @@
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{
+    Example, IntoPipelineData, PipelineData, ShellError, Signature, SyntaxShape, Type, Value,
+};
+use uuid::Uuid;
+
+use super::super::{COMMANDS_TOML, JupyterCommandContext};
+use crate::jupyter::messages::comm::CommOpen;
+use crate::jupyter::messages::iopub::IopubBroacast;
+
+#[derive(Debug, Clone)]
+pub struct Open(JupyterCommandContext);
+
+impl Open {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Open {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.comm_open.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.comm_open.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.comm_open.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.comm_open.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("target", SyntaxShape::String, "Target name of the comm")
+            .optional("data", SyntaxShape::Any, "Data to send when opening")
+            .input_output_types(vec![(Type::Nothing, Type::String)])
+            .category(super::super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .comm_open
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let target_name: String = call.req(engine_state, stack, 0)?;
+        let data: Option<Value> = call.opt(engine_state, stack, 1)?;
+        let data = match data {
+            Some(data) => self.0.to_json(engine_state, stack, &data)?,
+            None => serde_json::Value::Object(Default::default()),
+        };
+
+        let comm_id = Uuid::new_v4().to_string();
+        self.0
+            .comms
+            .lock()
+            .open(comm_id.clone(), target_name.clone());
+
+        let broadcast = IopubBroacast::CommOpen(CommOpen {
+            comm_id: comm_id.clone(),
+            target_name,
+            data,
+        });
+        self.0.publish(stack, call.head, broadcast)?;
+
+        Ok(Value::string(comm_id, call.head).into_pipeline_data())
+    }
+}
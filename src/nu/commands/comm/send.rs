@@ -0,0 +1,81 @@
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{
+    Example, PipelineData, ShellError, Signature, Spanned, SyntaxShape, Type, Value,
+};
+
+use super::super::{COMMANDS_TOML, JupyterCommandContext};
+use crate::jupyter::messages::comm::CommMsg;
+use crate::jupyter::messages::iopub::IopubBroacast;
+
+#[derive(Debug, Clone)]
+pub struct Send(JupyterCommandContext);
+
+impl Send {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Send {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.comm_send.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.comm_send.description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.comm_send.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("comm_id", SyntaxShape::String, "Id of an open comm")
+            .required("data", SyntaxShape::Any, "Data to send")
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .category(super::super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .comm_send
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let comm_id: Spanned<String> = call.req(engine_state, stack, 0)?;
+        let data: Value = call.req(engine_state, stack, 1)?;
+        let data = self.0.to_json(engine_state, stack, &data)?;
+
+        if !self.0.comms.lock().is_open(&comm_id.item) {
+            return Err(ShellError::IncorrectValue {
+                msg: "no open comm with this id".to_owned(),
+                val_span: comm_id.span,
+                call_span: call.head,
+            });
+        }
+
+        let broadcast = IopubBroacast::CommMsg(CommMsg {
+            comm_id: comm_id.item,
+            data,
+        });
+        self.0.publish(stack, call.head, broadcast)?;
+
+        Ok(PipelineData::Empty)
+    }
+}
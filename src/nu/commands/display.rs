@@ -1,9 +1,8 @@
-use mime_guess::MimeGuess;
 use nu_engine::CallExt;
 use nu_protocol::engine::Command;
 use nu_protocol::{Example, ShellError, Signature, Spanned, SyntaxShape, Type};
 
-use super::COMMANDS_TOML;
+use super::{COMMANDS_TOML, format_mime};
 use crate::handlers::shell::RENDER_FILTER;
 
 #[derive(Debug, Clone)]
@@ -55,12 +54,10 @@ impl Command for Display {
     ) -> Result<nu_protocol::PipelineData, ShellError> {
         let format: Spanned<String> = call.req(engine_state, stack, 0)?;
 
-        let mime = MimeGuess::from_ext(&format.item).first().ok_or_else(|| {
-            ShellError::IncorrectValue {
-                msg: "cannot guess a mime type".to_owned(),
-                val_span: format.span,
-                call_span: call.head,
-            }
+        let mime = format_mime(&format.item).ok_or_else(|| ShellError::IncorrectValue {
+            msg: "cannot guess a mime type".to_owned(),
+            val_span: format.span,
+            call_span: call.head,
         })?;
 
         RENDER_FILTER.lock().replace(mime);
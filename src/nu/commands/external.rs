@@ -80,4 +80,9 @@ impl External {
         }
         Ok(())
     }
+
+    /// Disable external commands again, used when the kernel restarts.
+    pub fn reset() {
+        EXTERNAL_STATE.store(ExternalState::Disabled, Ordering::SeqCst);
+    }
 }
@@ -0,0 +1,146 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use nu_engine::CallExt;
+use nu_protocol::engine::Command;
+use nu_protocol::{
+    Example, IntoPipelineData, PipelineData, ShellError, Signature, SyntaxShape, Type, Value,
+};
+
+use super::{COMMANDS_TOML, JupyterCommandContext};
+use crate::handlers::stdin::Prompt;
+use crate::jupyter::messages::stdin::{InputRequest, StdinRequest};
+use crate::jupyter::messages::{Header, Message, Metadata};
+
+/// Interval to check for interrupts while waiting for the frontend.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct Input(JupyterCommandContext);
+
+impl Input {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Input {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.input.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.input.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.input.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.input.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .optional("prompt", SyntaxShape::String, "Prompt to show the user.")
+            .named(
+                "default",
+                SyntaxShape::String,
+                "default value if no input is provided",
+                Some('d'),
+            )
+            .switch("suppress-output", "don't show the typed input", Some('s'))
+            .input_output_types(vec![(Type::Nothing, Type::String)])
+            .category(super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .input
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &nu_protocol::engine::EngineState,
+        stack: &mut nu_protocol::engine::Stack,
+        call: &nu_protocol::engine::Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let prompt: Option<String> = call.opt(engine_state, stack, 0)?;
+        let default: Option<String> = call.get_flag(engine_state, stack, "default")?;
+        let suppress_output = call.has_flag(engine_state, stack, "suppress-output")?;
+
+        let konst = self.0.konst.data(stack, call.head)?;
+        if !konst.execute.allow_stdin {
+            return Err(ShellError::GenericError {
+                error: "Input not supported".to_string(),
+                msg: "The frontend does not allow input requests.".to_string(),
+                span: Some(call.head),
+                help: Some("Run this cell in a frontend that supports input.".to_string()),
+                inner: vec![],
+            });
+        }
+
+        let prompt = match (prompt, &default) {
+            (Some(prompt), Some(default)) => format!("{prompt}(default: {default}) "),
+            (Some(prompt), None) => prompt,
+            (None, _) => String::new(),
+        };
+        let request = StdinRequest::Input(InputRequest {
+            prompt,
+            password: suppress_output,
+        });
+        let message = Message {
+            zmq_identities: konst.message.zmq_identities,
+            header: Header::new(request.msg_type()),
+            parent_header: Some(konst.message.header),
+            metadata: Metadata::empty(),
+            content: request,
+            buffers: vec![],
+        };
+
+        let request = message
+            .into_multipart()
+            .map_err(|err| ShellError::NushellFailed {
+                msg: err.to_string(),
+            })?;
+
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.0
+            .stdin
+            .blocking_send(Prompt {
+                request,
+                reply: reply_tx,
+            })
+            .map_err(|_| ShellError::NushellFailed {
+                msg: "stdin handler is not running".to_string(),
+            })?;
+
+        let value = loop {
+            match reply_rx.recv_timeout(INTERRUPT_POLL_INTERVAL) {
+                Ok(value) => break value,
+                Err(RecvTimeoutError::Timeout) => engine_state.signals().check(&call.head)?,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(ShellError::NushellFailed {
+                        msg: "stdin handler dropped the input request".to_string(),
+                    });
+                }
+            }
+        };
+
+        let value = match (value.is_empty(), default) {
+            (true, Some(default)) => default,
+            _ => value,
+        };
+        Ok(Value::string(value, call.head).into_pipeline_data())
+    }
+}
@@ -1,18 +1,37 @@
+use std::collections::HashSet;
 use std::fmt::Write;
+use std::sync::Arc;
 
-use nu_protocol::Category;
+use mime::Mime;
+use mime_guess::MimeGuess;
 use nu_protocol::engine::{EngineState, Stack, StateWorkingSet};
+use nu_protocol::{Category, ShellError, Span, Value};
+use parking_lot::Mutex;
 use tokio::sync::mpsc;
 
+use self::comm::SharedCommRegistry;
+use super::json::value_to_json;
 use super::konst::Konst;
 use super::module::KernelInternalSpans;
-use super::render::FormatDeclIds;
+use super::render::registry::SharedRendererRegistry;
+use super::render::{FormatDeclIds, TableLimits};
+use super::settings::SharedSettings;
+use crate::handlers::stdin::Prompt;
+use crate::jupyter::messages::iopub::IopubBroacast;
 use crate::jupyter::messages::multipart::Multipart;
+use crate::jupyter::messages::{Header, Message, Metadata};
 
+pub mod clear;
+pub mod comm;
 pub mod command;
 pub mod display;
 pub mod external;
+pub mod input;
 pub mod print;
+pub mod render;
+pub mod settings;
+pub mod update;
+pub mod usage;
 
 static_toml::static_toml! {
     const COMMANDS_TOML = include_toml!("commands.toml");
@@ -24,7 +43,7 @@ pub fn hide_incompatible_commands(
 ) -> Result<(), super::ExecuteError> {
     let mut code = String::new();
     for command in COMMANDS_TOML.incompatible_commands {
-        writeln!(code, "hide {command}").expect("String::write is infallible");
+        writeln!(code, "hide '{command}'").expect("String::write is infallible");
     }
 
     let mut stack = Stack::new();
@@ -36,14 +55,72 @@ pub fn category() -> Category {
     Category::Custom("jupyter".to_owned())
 }
 
+/// Mime type of a format, either a mime type like `text/latex` or a file
+/// extension like `md`.
+fn format_mime(format: &str) -> Option<Mime> {
+    match format.contains('/') {
+        true => format.parse().ok(),
+        false => MimeGuess::from_ext(format).first(),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct JupyterCommandContext {
     pub iopub: mpsc::Sender<Multipart>,
+    pub stdin: mpsc::Sender<Prompt>,
+    pub comms: SharedCommRegistry,
+    pub renderers: SharedRendererRegistry,
+    pub settings: SharedSettings,
+    /// Display ids used by `nuju print`, these can be updated by `nuju update`.
+    pub display_ids: Arc<Mutex<HashSet<String>>>,
     pub format_decl_ids: FormatDeclIds,
     pub konst: Konst,
     pub spans: KernelInternalSpans,
 }
 
+impl JupyterCommandContext {
+    /// Convert data for the frontend into json, ranges are limited like
+    /// rendered tables.
+    pub fn to_json(
+        &self,
+        engine_state: &EngineState,
+        stack: &Stack,
+        value: &Value,
+    ) -> Result<serde_json::Value, ShellError> {
+        let config = stack.get_config(engine_state);
+        let limits = TableLimits::new(&self.settings.lock().table, &config);
+        value_to_json(value, engine_state.signals(), limits.max_rows)
+    }
+
+    /// Publish a broadcast on iopub as part of the current cell.
+    pub fn publish(
+        &self,
+        stack: &Stack,
+        span: Span,
+        broadcast: IopubBroacast,
+    ) -> Result<(), ShellError> {
+        let konst = self.konst.data(stack, span)?;
+        let message = Message {
+            zmq_identities: konst.message.zmq_identities,
+            header: Header::new(broadcast.msg_type()),
+            parent_header: Some(konst.message.header),
+            metadata: Metadata::empty(),
+            content: broadcast,
+            buffers: vec![],
+        };
+        let multipart = message
+            .into_multipart()
+            .map_err(|err| ShellError::NushellFailed {
+                msg: err.to_string(),
+            })?;
+        self.iopub
+            .blocking_send(multipart)
+            .map_err(|_| ShellError::NushellFailed {
+                msg: "iopub handler is not running".to_string(),
+            })
+    }
+}
+
 pub fn add_jupyter_command_context(
     mut engine_state: EngineState,
     ctx: JupyterCommandContext,
@@ -59,9 +136,22 @@ pub fn add_jupyter_command_context(
 
         bind_command! {
             command::Nuju,
+            clear::Clear::new(ctx.clone()),
+            comm::Comm,
+            comm::close::Close::new(ctx.clone()),
+            comm::on_msg::OnMsg::new(ctx.clone()),
+            comm::open::Open::new(ctx.clone()),
+            comm::send::Send::new(ctx.clone()),
             external::External,
             display::Display,
-            print::Print::new(ctx)
+            input::Input::new(ctx.clone()),
+            print::Print::new(ctx.clone()),
+            render::Render,
+            render::register::Register::new(ctx.clone()),
+            render::unregister::Unregister::new(ctx.clone()),
+            settings::Settings::new(ctx.clone()),
+            update::Update::new(ctx),
+            usage::Usage,
         }
 
         working_set.render()
@@ -1,13 +1,11 @@
 use std::collections::HashMap;
 
-use mime_guess::MimeGuess;
 use nu_engine::CallExt;
-use nu_protocol::engine::Command;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
 use nu_protocol::{FromValue, PipelineData, ShellError, Signature, Span, SyntaxShape, Type, Value};
 
-use super::{COMMANDS_TOML, JupyterCommandContext};
+use super::{COMMANDS_TOML, JupyterCommandContext, format_mime};
 use crate::jupyter::messages::iopub::{DisplayData, IopubBroacast};
-use crate::jupyter::messages::{Header, Message, Metadata};
 use crate::nu::render::{PipelineRender, StringifiedPipelineRender};
 
 #[derive(Debug, Clone)]
@@ -41,6 +39,12 @@ impl Command for Print {
                 "Format to filter for",
                 Some('f'),
             )
+            .named(
+                "display-id",
+                SyntaxShape::String,
+                "Id to update this output later via `nuju update`",
+                Some('d'),
+            )
             .input_output_types(vec![
                 (Type::Any, Type::Nothing),
                 (Type::Nothing, Type::Nothing),
@@ -48,7 +52,6 @@ impl Command for Print {
             .category(super::category())
     }
 
-    // TODO: split this into multiple parts, this is too much
     fn run(
         &self,
         engine_state: &nu_protocol::engine::EngineState,
@@ -56,92 +59,113 @@ impl Command for Print {
         call: &nu_protocol::engine::Call,
         input: nu_protocol::PipelineData,
     ) -> Result<nu_protocol::PipelineData, ShellError> {
-        let arg: Option<Value> = call.opt(engine_state, stack, 0)?;
-        let arg: Option<PipelineData> = arg.map(|v| PipelineData::Value(v, None));
-        let input_span = input.span(); // maybe needed for an error
-        let to_render = match (input, arg) {
-            // no data provided, throw error
-            (PipelineData::Empty, None) => Err(ShellError::GenericError {
-                error: "No input data".to_string(),
-                msg: "No data was piped or passed as an argument to the command.".to_string(),
-                span: Some(call.span()),
-                help: Some(
-                    "Please provide data through the pipeline or as an argument.".to_string(),
-                ),
-                inner: vec![],
-            }),
-
-            // passed arg has no data, throw error
-            (_, Some(PipelineData::Empty)) => Err(ShellError::TypeMismatch {
-                err_message: "Expected non-empty data, but found empty".to_string(),
-                span: call.arguments_span(),
-            }),
-
-            // render passed arg
-            (PipelineData::Empty, Some(data)) => Ok(data),
-
-            // too many inputs, throw error
-            (_, Some(_)) => Err(ShellError::IncompatibleParameters {
-                left_message: "Either pass data via pipe".to_string(),
-                left_span: input_span.unwrap_or(call.head),
-                right_message: "Or pass data via an argument".to_string(),
-                right_span: call.arguments_span(),
-            }),
-
-            // render piped arg
-            (data, None) => Ok(data),
-        }?;
-
-        let format: Option<Value> = call.get_flag(engine_state, stack, "format")?;
-        let spanned_format: Option<(Span, Value)> = format.map(|v| (v.span(), v));
-        let spanned_format: Option<(Span, String)> = spanned_format
-            .map(|(span, v)| String::from_value(v).map(|s| (span, s)))
-            .transpose()?;
-        let mime = spanned_format
-            .map(|(span, s)| {
-                MimeGuess::from_ext(&s)
-                    .first()
-                    .ok_or_else(|| ShellError::IncorrectValue {
-                        msg: "Cannot guess a mime type".to_owned(),
-                        val_span: span,
-                        call_span: call.head,
-                    })
-            })
-            .transpose()?;
-
-        let render: StringifiedPipelineRender = PipelineRender::render(
-            to_render,
-            engine_state,
-            stack,
-            &self.0.spans,
-            self.0.format_decl_ids,
-            mime,
-        )
-        .unwrap() // TODO: handle this better
-        .into();
+        let to_render = display_input(engine_state, stack, call, input, 0)?;
+        let render = render(&self.0, engine_state, stack, call, to_render)?;
+
+        let display_id: Option<String> = call.get_flag(engine_state, stack, "display-id")?;
+        let konst = self.0.konst.data(stack, call.head)?;
+        if konst.execute.silent {
+            return Ok(PipelineData::Empty);
+        }
+
+        // only displayed outputs can be updated later
+        let mut transient = HashMap::new();
+        if let Some(display_id) = display_id {
+            self.0.display_ids.lock().insert(display_id.clone());
+            transient.insert("display_id".to_owned(), display_id);
+        }
 
         let display_data = DisplayData {
             data: render.data,
             metadata: render.metadata,
-            transient: HashMap::new(),
+            transient,
         };
         let broadcast = IopubBroacast::DisplayData(display_data);
-
-        let konst = self.0.konst.data(stack, call.head)?;
-        let message = Message {
-            zmq_identities: konst.message.zmq_identities,
-            header: Header::new(broadcast.msg_type()),
-            parent_header: Some(konst.message.header),
-            metadata: Metadata::empty(),
-            content: broadcast,
-            buffers: vec![],
-        };
-
-        self.0
-            .iopub
-            .blocking_send(message.into_multipart().unwrap())
-            .unwrap();
+        self.0.publish(stack, call.head, broadcast)?;
 
         Ok(PipelineData::Empty)
     }
 }
+
+/// Get the data to display, either piped in or passed as the argument at
+/// `arg_pos`.
+pub(super) fn display_input(
+    engine_state: &EngineState,
+    stack: &mut Stack,
+    call: &Call,
+    input: PipelineData,
+    arg_pos: usize,
+) -> Result<PipelineData, ShellError> {
+    let arg: Option<Value> = call.opt(engine_state, stack, arg_pos)?;
+    let arg: Option<PipelineData> = arg.map(|v| PipelineData::Value(v, None));
+    let input_span = input.span(); // maybe needed for an error
+    match (input, arg) {
+        // no data provided, throw error
+        (PipelineData::Empty, None) => Err(ShellError::GenericError {
+            error: "No input data".to_string(),
+            msg: "No data was piped or passed as an argument to the command.".to_string(),
+            span: Some(call.span()),
+            help: Some("Please provide data through the pipeline or as an argument.".to_string()),
+            inner: vec![],
+        }),
+
+        // passed arg has no data, throw error
+        (_, Some(PipelineData::Empty)) => Err(ShellError::TypeMismatch {
+            err_message: "Expected non-empty data, but found empty".to_string(),
+            span: call.arguments_span(),
+        }),
+
+        // render passed arg
+        (PipelineData::Empty, Some(data)) => Ok(data),
+
+        // too many inputs, throw error
+        (_, Some(_)) => Err(ShellError::IncompatibleParameters {
+            left_message: "Either pass data via pipe".to_string(),
+            left_span: input_span.unwrap_or(call.head),
+            right_message: "Or pass data via an argument".to_string(),
+            right_span: call.arguments_span(),
+        }),
+
+        // render piped arg
+        (data, None) => Ok(data),
+    }
+}
+
+/// Render the data, filtered by the `--format` flag if passed.
+pub(super) fn render(
+    ctx: &JupyterCommandContext,
+    engine_state: &EngineState,
+    stack: &mut Stack,
+    call: &Call,
+    to_render: PipelineData,
+) -> Result<StringifiedPipelineRender, ShellError> {
+    let format: Option<Value> = call.get_flag(engine_state, stack, "format")?;
+    let spanned_format: Option<(Span, Value)> = format.map(|v| (v.span(), v));
+    let spanned_format: Option<(Span, String)> = spanned_format
+        .map(|(span, v)| String::from_value(v).map(|s| (span, s)))
+        .transpose()?;
+    let mime = spanned_format
+        .map(|(span, s)| {
+            format_mime(&s).ok_or_else(|| ShellError::IncorrectValue {
+                msg: "Cannot guess a mime type".to_owned(),
+                val_span: span,
+                call_span: call.head,
+            })
+        })
+        .transpose()?;
+
+    let konst = ctx.konst.data(stack, call.head)?;
+    let renderers = ctx.renderers.lock().shell(konst.subshell_id.as_deref());
+    Ok(PipelineRender::render(
+        to_render,
+        engine_state,
+        stack,
+        &ctx.spans,
+        ctx.format_decl_ids,
+        &renderers,
+        &ctx.settings,
+        mime,
+    )
+    .map_err(ShellError::from)?
+    .into())
+}
@@ -0,0 +1,48 @@
+use nu_engine::get_full_help;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{IntoPipelineData, PipelineData, ShellError, Signature, Type, Value};
+
+use super::COMMANDS_TOML;
+
+pub mod register;
+pub mod unregister;
+
+#[derive(Clone)]
+pub struct Render;
+
+impl Command for Render {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.render.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.render.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.render.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.render.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(Self.name())
+            .category(super::category())
+            .input_output_types(vec![(Type::Nothing, Type::String)])
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        Ok(
+            Value::string(get_full_help(&Render, engine_state, stack), call.head)
+                .into_pipeline_data(),
+        )
+    }
+}
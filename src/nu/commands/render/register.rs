@@ -0,0 +1,90 @@
+use mime::Mime;
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Closure, Command, EngineState, Stack};
+use nu_protocol::{Example, PipelineData, ShellError, Signature, Spanned, SyntaxShape, Type};
+
+use super::super::{COMMANDS_TOML, JupyterCommandContext};
+use crate::nu::render::registry::Renderer;
+
+#[derive(Debug, Clone)]
+pub struct Register(JupyterCommandContext);
+
+impl Register {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Register {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.render_register.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.render_register.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.render_register.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.render_register.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("mime", SyntaxShape::String, "Mime type to render")
+            .required(
+                "renderer",
+                SyntaxShape::Closure(Some(vec![SyntaxShape::Any])),
+                "Closure called with the value to render",
+            )
+            .named(
+                "when",
+                SyntaxShape::Closure(Some(vec![SyntaxShape::Any])),
+                "Closure deciding whether a value is rendered",
+                Some('w'),
+            )
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .category(super::super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .render_register
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let mime: Spanned<String> = call.req(engine_state, stack, 0)?;
+        let closure: Closure = call.req(engine_state, stack, 1)?;
+        let when: Option<Closure> = call.get_flag(engine_state, stack, "when")?;
+
+        let mime: Mime = mime.item.parse().map_err(|_| ShellError::IncorrectValue {
+            msg: "invalid mime type".to_owned(),
+            val_span: mime.span,
+            call_span: call.head,
+        })?;
+        let konst = self.0.konst.data(stack, call.head)?;
+        self.0
+            .renderers
+            .lock()
+            .shell_mut(konst.subshell_id.as_deref())
+            .register(mime, Renderer::new(closure, when));
+        Ok(PipelineData::Empty)
+    }
+}
@@ -0,0 +1,75 @@
+use mime::Mime;
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{Example, PipelineData, ShellError, Signature, Spanned, SyntaxShape, Type};
+
+use super::super::{COMMANDS_TOML, JupyterCommandContext};
+
+#[derive(Debug, Clone)]
+pub struct Unregister(JupyterCommandContext);
+
+impl Unregister {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Unregister {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.render_unregister.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.render_unregister.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.render_unregister.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.render_unregister.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("mime", SyntaxShape::String, "Mime type to stop rendering")
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .category(super::super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .render_unregister
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let mime: Spanned<String> = call.req(engine_state, stack, 0)?;
+        let mime: Mime = mime.item.parse().map_err(|_| ShellError::IncorrectValue {
+            msg: "invalid mime type".to_owned(),
+            val_span: mime.span,
+            call_span: call.head,
+        })?;
+        let konst = self.0.konst.data(stack, call.head)?;
+        self.0
+            .renderers
+            .lock()
+            .shell_mut(konst.subshell_id.as_deref())
+            .unregister(&mime);
+        Ok(PipelineData::Empty)
+    }
+}
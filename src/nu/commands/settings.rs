@@ -0,0 +1,69 @@
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{
+    Example, IntoPipelineData, IntoValue, PipelineData, ShellError, Signature, Type,
+};
+
+use super::{COMMANDS_TOML, JupyterCommandContext};
+
+#[derive(Debug, Clone)]
+pub struct Settings(JupyterCommandContext);
+
+impl Settings {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Settings {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.settings.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.settings.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.settings.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.settings.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .input_output_types(vec![
+                (Type::Nothing, Type::record()),
+                (Type::record(), Type::record()),
+            ])
+            .category(super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .settings
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        _engine_state: &EngineState,
+        _stack: &mut Stack,
+        call: &Call,
+        input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let mut settings = self.0.settings.lock();
+        if !input.is_nothing() {
+            settings.update(&input.into_value(call.head)?, call.head)?;
+        }
+        Ok(settings.clone().into_value(call.head).into_pipeline_data())
+    }
+}
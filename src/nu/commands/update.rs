@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use nu_engine::CallExt;
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{Example, PipelineData, ShellError, Signature, Spanned, SyntaxShape, Type};
+
+use super::print::{display_input, render};
+use super::{COMMANDS_TOML, JupyterCommandContext};
+use crate::jupyter::messages::iopub::{DisplayData, IopubBroacast};
+
+#[derive(Debug, Clone)]
+pub struct Update(JupyterCommandContext);
+
+impl Update {
+    pub fn new(ctx: JupyterCommandContext) -> Self {
+        Self(ctx)
+    }
+}
+
+impl Command for Update {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.update.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.update.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.update.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.update.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required(
+                "display_id",
+                SyntaxShape::String,
+                "Id passed to `nuju print --display-id`",
+            )
+            .optional("input", SyntaxShape::Any, "Value to display instead")
+            .named(
+                "format",
+                SyntaxShape::String,
+                "Format to filter for",
+                Some('f'),
+            )
+            .input_output_types(vec![
+                (Type::Any, Type::Nothing),
+                (Type::Nothing, Type::Nothing),
+            ])
+            .category(super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .update
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let display_id: Spanned<String> = call.req(engine_state, stack, 0)?;
+        if !self.0.display_ids.lock().contains(&display_id.item) {
+            return Err(ShellError::IncorrectValue {
+                msg: "no output with this display id".to_owned(),
+                val_span: display_id.span,
+                call_span: call.head,
+            });
+        }
+
+        let to_render = display_input(engine_state, stack, call, input, 1)?;
+        let render = render(&self.0, engine_state, stack, call, to_render)?;
+
+        let display_data = DisplayData {
+            data: render.data,
+            metadata: render.metadata,
+            transient: HashMap::from([("display_id".to_owned(), display_id.item)]),
+        };
+        let broadcast = IopubBroacast::UpdateDisplayData(display_data);
+
+        let konst = self.0.konst.data(stack, call.head)?;
+        if konst.execute.silent {
+            return Ok(PipelineData::Empty);
+        }
+        self.0.publish(stack, call.head, broadcast)?;
+
+        Ok(PipelineData::Empty)
+    }
+}
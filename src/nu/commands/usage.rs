@@ -0,0 +1,58 @@
+use nu_protocol::engine::{Call, Command, EngineState, Stack};
+use nu_protocol::{
+    Example, IntoPipelineData, IntoValue, PipelineData, ShellError, Signature, Type,
+};
+
+use super::COMMANDS_TOML;
+use crate::jupyter::usage::ResourceUsage;
+
+#[derive(Debug, Clone)]
+pub struct Usage;
+
+impl Command for Usage {
+    fn name(&self) -> &str {
+        COMMANDS_TOML.usage.name
+    }
+
+    fn description(&self) -> &str {
+        COMMANDS_TOML.usage.description
+    }
+
+    fn extra_description(&self) -> &str {
+        COMMANDS_TOML.usage.extra_description
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        COMMANDS_TOML.usage.search_terms.into()
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .category(super::category())
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        COMMANDS_TOML
+            .usage
+            .examples
+            .iter()
+            .map(|eg| Example {
+                example: eg.example,
+                description: eg.description,
+                result: None,
+            })
+            .collect()
+    }
+
+    fn run(
+        &self,
+        _engine_state: &EngineState,
+        _stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let usage = ResourceUsage::measure();
+        Ok(usage.into_value(call.head).into_pipeline_data())
+    }
+}
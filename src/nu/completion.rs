@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use nu_cli::{NuCompleter, SemanticSuggestion};
+use nu_protocol::SuggestionKind;
+use nu_protocol::engine::{CommandType, EngineState, Stack};
+
+use crate::util::{byte_to_char_offset, char_to_byte_offset};
+
+/// Completions for a cursor position in a cell.
+///
+/// All positions are counted in unicode code points as Jupyter expects them.
+#[derive(Debug)]
+pub struct Completions {
+    pub matches: Vec<CompletionMatch>,
+    pub cursor_start: usize,
+    pub cursor_end: usize,
+}
+
+#[derive(Debug)]
+pub struct CompletionMatch {
+    /// Text that replaces `cursor_start..cursor_end` in the cell.
+    pub text: String,
+    pub kind: Option<SuggestionKind>,
+    pub description: Option<String>,
+}
+
+impl CompletionMatch {
+    /// Type name as used by JupyterLab to pick an icon for the completion.
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            Some(SuggestionKind::Command(CommandType::Keyword, _)) => "keyword",
+            Some(SuggestionKind::Command(..)) => "function",
+            Some(SuggestionKind::Value(_)) => "instance",
+            Some(SuggestionKind::CellPath) => "property",
+            Some(SuggestionKind::Directory | SuggestionKind::File) => "path",
+            Some(SuggestionKind::Flag) => "param",
+            Some(SuggestionKind::Module) => "module",
+            Some(SuggestionKind::Operator) => "keyword",
+            Some(SuggestionKind::Variable) => "instance",
+            None => "<unknown>",
+        }
+    }
+}
+
+/// Complete `code` at `cursor_pos` using the live engine state and stack.
+///
+/// This uses the same completer as the nu REPL, so commands, subcommands,
+/// flags, variables, cell paths and file paths are all suggested.
+pub fn complete(
+    engine_state: &EngineState,
+    stack: &Stack,
+    code: &str,
+    cursor_pos: usize,
+) -> Completions {
+    let byte_pos = char_to_byte_offset(code, cursor_pos);
+    let completer = NuCompleter::new(Arc::new(engine_state.clone()), Arc::new(stack.clone()));
+    let suggestions = completer.fetch_completions_at(code, byte_pos);
+
+    // Jupyter only allows a single replacement span for all matches, so we use
+    // the widest one and pad the narrower suggestions with the text they keep
+    let byte_start = suggestions
+        .iter()
+        .map(|s| s.suggestion.span.start)
+        .min()
+        .unwrap_or(byte_pos);
+    let byte_end = suggestions
+        .iter()
+        .map(|s| s.suggestion.span.end)
+        .max()
+        .unwrap_or(byte_pos);
+
+    let matches = suggestions
+        .into_iter()
+        .filter_map(|SemanticSuggestion { suggestion, kind }| {
+            let prefix = code.get(byte_start..suggestion.span.start)?;
+            let suffix = code.get(suggestion.span.end..byte_end)?;
+            Some(CompletionMatch {
+                text: format!("{prefix}{}{suffix}", suggestion.value),
+                kind,
+                description: suggestion.description,
+            })
+        })
+        .collect();
+
+    Completions {
+        matches,
+        cursor_start: byte_to_char_offset(code, byte_start),
+        cursor_end: byte_to_char_offset(code, byte_end),
+    }
+}
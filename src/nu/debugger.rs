@@ -0,0 +1,761 @@
+//! Debugger for the Jupyter debugger protocol.
+//!
+//! Cells run on a blocking thread while the control handler answers debug
+//! requests.
+//! Both sides share a [`DebugSession`], the [`JupyterDebugger`] hooked into
+//! the evaluation pauses the cell on breakpoints and steps until the control
+//! handler resumes it.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fmt, fs, io, process};
+
+use nu_protocol::ast::Block;
+use nu_protocol::debugger::Debugger;
+use nu_protocol::engine::{CachedFile, EngineState, Stack};
+use nu_protocol::ir::{Instruction, IrBlock};
+use nu_protocol::{Config, PipelineData, PipelineExecutionData, Span, Value};
+use parking_lot::{Condvar, Mutex};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::error::MessageError;
+use crate::jupyter::messages::debug::DebugEvent;
+use crate::jupyter::messages::iopub::IopubBroacast;
+use crate::jupyter::messages::message_type::MessageType;
+use crate::jupyter::messages::multipart::Multipart;
+use crate::jupyter::messages::{Header, Message, Metadata};
+
+/// Nushell evaluates cells on a single thread.
+pub const THREAD_ID: usize = 1;
+
+/// Seed for hashing cell sources, same as ipykernel uses.
+pub const HASH_SEED: u32 = 0xC70F6907;
+
+/// How often a paused cell checks for interrupts.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// MurmurHash2 of the UTF-8 bytes, used by frontends to name cell sources.
+pub fn murmur2(data: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0x5BD1E995;
+
+    let mut h = seed ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in chunks.by_ref() {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, byte) in rest.iter().enumerate() {
+            h ^= (*byte as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^ (h >> 15)
+}
+
+/// Position of an instruction inside of a cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Name of the cell, like `cell[1]#1`.
+    pub cell: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    fn same_line(&self, other: &Location) -> bool {
+        self.cell == other.cell && self.line == other.line
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    pub location: Option<Location>,
+    /// Variables stored while evaluating this frame.
+    pub locals: Vec<(String, Value)>,
+}
+
+/// How a paused cell should continue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    Next,
+    StepIn,
+    StepOut,
+}
+
+#[derive(Debug, Clone, Default)]
+enum Step {
+    #[default]
+    Continue,
+    Pause,
+    Over {
+        depth: usize,
+        from: Location,
+    },
+    In {
+        from: Location,
+    },
+    Out {
+        depth: usize,
+    },
+}
+
+/// State of a paused cell, variables are referenced by their index in
+/// `variables` plus one.
+#[derive(Debug)]
+pub struct Stopped {
+    /// Frames with the innermost first.
+    pub frames: Vec<Frame>,
+    location: Location,
+    depth: usize,
+    config: Arc<Config>,
+    variables: Vec<Vec<(String, Value)>>,
+    /// References of nested variables by the reference and index of their
+    /// parent, so that repeated requests reuse them.
+    children: HashMap<(usize, usize), usize>,
+    /// Reference of the globals scope.
+    globals: usize,
+}
+
+impl Stopped {
+    /// References of the local and global scope of a frame.
+    pub fn scopes(&self, frame_id: usize) -> Option<(usize, usize)> {
+        match frame_id < self.frames.len() {
+            true => Some((frame_id + 1, self.globals)),
+            false => None,
+        }
+    }
+
+    /// Variables of a reference as DAP `Variable`s.
+    ///
+    /// Records and lists get a reference for their children.
+    pub fn variables(&mut self, reference: usize) -> Vec<serde_json::Value> {
+        let Some(index) = reference
+            .checked_sub(1)
+            .filter(|index| *index < self.variables.len())
+        else {
+            return vec![];
+        };
+
+        let mut references = Vec::new();
+        for child in 0..self.variables[index].len() {
+            let value = &self.variables[index][child].1;
+            let nested = match value {
+                Value::Record { val, .. } if !val.is_empty() => val
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect(),
+                Value::List { vals, .. } if !vals.is_empty() => vals
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| (index.to_string(), value.clone()))
+                    .collect(),
+                _ => {
+                    references.push(0);
+                    continue;
+                }
+            };
+            let next = self.variables.len() + 1;
+            let child_reference = *self.children.entry((reference, child)).or_insert(next);
+            if child_reference == next {
+                self.variables.push(nested);
+            }
+            references.push(child_reference);
+        }
+
+        self.variables[index]
+            .iter()
+            .zip(references)
+            .map(|((name, value), reference)| variable(name, value, &self.config, reference))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct CellSource {
+    path: String,
+    code: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SessionState {
+    pub attached: bool,
+    /// Breakpoint lines by source path or cell name.
+    pub breakpoints: HashMap<String, BTreeSet<usize>>,
+    cells: HashMap<String, CellSource>,
+    globals: Vec<(String, Value)>,
+    step: Step,
+    pub stopped: Option<Stopped>,
+}
+
+impl SessionState {
+    fn is_breakpoint(&self, location: &Location) -> bool {
+        let path = self
+            .cells
+            .get(&location.cell)
+            .map(|cell| cell.path.as_str());
+        [Some(location.cell.as_str()), path]
+            .into_iter()
+            .flatten()
+            .filter_map(|source| self.breakpoints.get(source))
+            .any(|lines| lines.contains(&location.line))
+    }
+
+    fn stop_reason(&self, location: &Location, depth: usize) -> Option<&'static str> {
+        if self.is_breakpoint(location) {
+            return Some("breakpoint");
+        }
+        match &self.step {
+            Step::Continue => None,
+            Step::Pause => Some("pause"),
+            Step::Over { depth: d, from } if depth <= *d && !location.same_line(from) => {
+                Some("step")
+            }
+            Step::In { from } if !location.same_line(from) => Some("step"),
+            Step::Out { depth: d } if depth < *d => Some("step"),
+            Step::Over { .. } | Step::In { .. } | Step::Out { .. } => None,
+        }
+    }
+
+    /// Source of a cell by its path or name.
+    pub fn source(&self, path_or_name: &str) -> Option<&str> {
+        self.cells
+            .iter()
+            .find(|(name, cell)| *name == path_or_name || cell.path == path_or_name)
+            .map(|(_, cell)| cell.code.as_str())
+    }
+
+    pub fn globals(&self) -> &[(String, Value)] {
+        &self.globals
+    }
+
+    fn path(&self, cell: &str) -> Option<&str> {
+        self.cells.get(cell).map(|cell| cell.path.as_str())
+    }
+
+    /// Frames of the paused cell as DAP `StackFrame`s, the innermost first.
+    pub fn stack_frames(&self) -> Vec<serde_json::Value> {
+        let Some(stopped) = &self.stopped
+        else {
+            return vec![];
+        };
+        stopped
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(id, frame)| match &frame.location {
+                Some(location) => json!({
+                    "id": id,
+                    "name": frame.name,
+                    "line": location.line,
+                    "column": location.column,
+                    "source": {
+                        "name": location.cell,
+                        "path": self.path(&location.cell).unwrap_or(&location.cell),
+                    },
+                }),
+                None => json!({
+                    "id": id,
+                    "name": frame.name,
+                    "line": 0,
+                    "column": 0,
+                }),
+            })
+            .collect()
+    }
+}
+
+/// Debug session shared between the control handler and running cells.
+#[derive(Debug)]
+pub struct DebugSession {
+    state: Mutex<SessionState>,
+    resumed: Condvar,
+    iopub: mpsc::Sender<Multipart>,
+    seq: Mutex<usize>,
+    tmp_dir: PathBuf,
+}
+
+impl DebugSession {
+    pub fn new(iopub: mpsc::Sender<Multipart>) -> Self {
+        let tmp_dir = env::temp_dir().join(format!("nu-jupyter-kernel_{}", process::id()));
+        DebugSession {
+            state: Mutex::new(SessionState::default()),
+            resumed: Condvar::new(),
+            iopub,
+            seq: Mutex::new(0),
+            tmp_dir,
+        }
+    }
+
+    pub fn state(&self) -> parking_lot::MutexGuard<'_, SessionState> {
+        self.state.lock()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.state.lock().attached
+    }
+
+    /// Next sequence number for responses and events.
+    pub fn next_seq(&self) -> usize {
+        let mut seq = self.seq.lock();
+        *seq += 1;
+        *seq
+    }
+
+    /// Prefix of the source paths of cells, the hash of the code follows.
+    pub fn tmp_file_prefix(&self) -> String {
+        let mut prefix = self.tmp_dir.display().to_string();
+        prefix.push(std::path::MAIN_SEPARATOR);
+        prefix
+    }
+
+    pub fn tmp_file_suffix(&self) -> &'static str {
+        ".nu"
+    }
+
+    /// Source path of some code, frontends calculate the same path to set
+    /// breakpoints before the cell runs.
+    pub fn source_path(&self, code: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.tmp_file_prefix(),
+            murmur2(code.as_bytes(), HASH_SEED),
+            self.tmp_file_suffix()
+        )
+    }
+
+    /// Write the code of a cell to its source path.
+    pub fn dump_cell(&self, code: &str) -> io::Result<String> {
+        let path = self.source_path(code);
+        fs::create_dir_all(&self.tmp_dir)?;
+        fs::write(&path, code)?;
+        Ok(path)
+    }
+
+    /// Remember the code of a cell, so that breakpoints on its source path
+    /// apply to it.
+    pub fn register_cell(&self, name: &str, code: &str) {
+        let path = self.source_path(code);
+        self.state.lock().cells.insert(name.to_owned(), CellSource {
+            path,
+            code: code.to_owned(),
+        });
+    }
+
+    pub fn set_globals(&self, globals: Vec<(String, Value)>) {
+        self.state.lock().globals = globals;
+    }
+
+    /// Resume a paused cell.
+    pub fn resume(&self, resume: Resume) -> bool {
+        let mut state = self.state.lock();
+        let Some(stopped) = state.stopped.take()
+        else {
+            return false;
+        };
+        state.step = match resume {
+            Resume::Continue => Step::Continue,
+            Resume::Next => Step::Over {
+                depth: stopped.depth,
+                from: stopped.location,
+            },
+            Resume::StepIn => Step::In {
+                from: stopped.location,
+            },
+            Resume::StepOut => Step::Out {
+                depth: stopped.depth,
+            },
+        };
+        self.resumed.notify_all();
+        true
+    }
+
+    /// Pause the cell at the next instruction.
+    pub fn pause(&self) {
+        self.state.lock().step = Step::Pause;
+    }
+
+    /// End the session and let paused cells continue.
+    pub fn detach(&self) {
+        let mut state = self.state.lock();
+        state.attached = false;
+        state.breakpoints.clear();
+        state.cells.clear();
+        state.globals.clear();
+        state.step = Step::Continue;
+        state.stopped = None;
+        self.resumed.notify_all();
+    }
+
+    /// Forget stepping after a cell finished.
+    pub fn finish_execution(&self) {
+        self.state.lock().step = Step::Continue;
+    }
+
+    /// Construct a `debug_event` message.
+    pub fn event(&self, event: &str, body: serde_json::Value) -> Result<Multipart, MessageError> {
+        let broadcast = IopubBroacast::DebugEvent(DebugEvent {
+            seq: self.next_seq(),
+            event: event.to_owned(),
+            body,
+        });
+        Message {
+            zmq_identities: vec![],
+            header: Header::new(MessageType::DebugEvent),
+            parent_header: None,
+            metadata: Metadata::empty(),
+            content: broadcast,
+            buffers: vec![],
+        }
+        .into_multipart()
+    }
+
+    pub async fn send_event(
+        &self,
+        event: &str,
+        body: serde_json::Value,
+    ) -> Result<(), MessageError> {
+        Ok(self.iopub.send(self.event(event, body)?).await?)
+    }
+
+    /// Send an event from the blocking thread of a cell.
+    fn blocking_event(&self, event: &str, body: serde_json::Value) {
+        let sent = self
+            .event(event, body)
+            .and_then(|multipart| Ok(self.iopub.blocking_send(multipart)?));
+        if let Err(err) = sent {
+            eprintln!("could not send debug event {event}: {err}");
+        }
+    }
+
+    /// Block the cell until it is resumed, detached or interrupted.
+    fn stop(
+        &self,
+        reason: &str,
+        location: Location,
+        frames: Vec<Frame>,
+        engine_state: &EngineState,
+    ) {
+        {
+            let mut state = self.state.lock();
+            let depth = frames.len();
+            let frames: Vec<Frame> = frames
+                .into_iter()
+                .rev()
+                .map(|mut frame| {
+                    if let Some(location) = &frame.location &&
+                        frame.name.is_empty()
+                    {
+                        frame.name = location.cell.clone();
+                    }
+                    frame
+                })
+                .collect();
+            let mut variables: Vec<_> = frames.iter().map(|frame| frame.locals.clone()).collect();
+            variables.push(state.globals.clone());
+            state.stopped = Some(Stopped {
+                globals: variables.len(),
+                frames,
+                location,
+                depth,
+                config: engine_state.get_config().clone(),
+                variables,
+                children: HashMap::new(),
+            });
+            state.step = Step::Continue;
+        }
+
+        self.blocking_event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+
+        let mut state = self.state.lock();
+        while state.stopped.is_some() {
+            self.resumed.wait_for(&mut state, INTERRUPT_POLL_INTERVAL);
+            if engine_state.signals().interrupted() {
+                state.stopped = None;
+            }
+        }
+        drop(state);
+
+        self.blocking_event(
+            "continued",
+            json!({"threadId": THREAD_ID, "allThreadsContinued": true}),
+        );
+    }
+}
+
+/// A value as DAP `Variable`, children are listed under the
+/// `variables_reference`.
+pub fn variable(
+    name: &str,
+    value: &Value,
+    config: &Config,
+    variables_reference: usize,
+) -> serde_json::Value {
+    json!({
+        "name": name,
+        "value": value.to_abbreviated_string(config),
+        "type": value.get_type().to_string(),
+        "variablesReference": variables_reference,
+    })
+}
+
+/// Named variables of the stack, the globals of a debug session.
+pub fn stack_variables(engine_state: &EngineState, stack: &Stack) -> Vec<(String, Value)> {
+    stack
+        .vars
+        .iter()
+        .filter_map(|(var_id, value)| {
+            let span = engine_state.get_var(*var_id).declaration_span;
+            if span == Span::unknown() {
+                return None;
+            }
+            let name = String::from_utf8_lossy(engine_state.get_span_contents(span));
+            Some((name.trim_start_matches('$').to_owned(), value.clone()))
+        })
+        .collect()
+}
+
+fn contains(file: &CachedFile, span: Span) -> bool {
+    file.covered_span.start <= span.start && span.start < file.covered_span.end
+}
+
+/// Debugger hooked into the evaluation of a cell.
+pub struct JupyterDebugger {
+    session: Arc<DebugSession>,
+    frames: Vec<Frame>,
+    /// Registered cell of every entered block, looked up once per block.
+    cells: Vec<Option<CachedFile>>,
+}
+
+impl fmt::Debug for JupyterDebugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JupyterDebugger")
+            .field("session", &self.session)
+            .field("frames", &self.frames)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JupyterDebugger {
+    pub fn new(session: Arc<DebugSession>) -> Self {
+        JupyterDebugger {
+            session,
+            frames: Vec::new(),
+            cells: Vec::new(),
+        }
+    }
+
+    /// Registered cell containing a span, only those can be debugged.
+    fn cell(&self, engine_state: &EngineState, span: Span) -> Option<CachedFile> {
+        let file = engine_state
+            .files()
+            .rev()
+            .find(|file| contains(file, span))?;
+        self.session.state.lock().path(&file.name)?;
+        Some(file.clone())
+    }
+
+    /// Location of a span inside of the cell of the current block.
+    fn locate(&self, span: Span) -> Option<Location> {
+        let file = self
+            .cells
+            .last()?
+            .as_ref()
+            .filter(|file| contains(file, span))?;
+        let offset = span.start - file.covered_span.start;
+        let before = &file.content[..offset];
+        let line = before.iter().filter(|byte| **byte == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+        Some(Location {
+            cell: file.name.to_string(),
+            line,
+            column: offset - line_start + 1,
+        })
+    }
+}
+
+impl Debugger for JupyterDebugger {
+    fn enter_block(&mut self, engine_state: &EngineState, block: &Block) {
+        let cell = block.span.and_then(|span| self.cell(engine_state, span));
+        self.cells.push(cell);
+
+        // the outermost frame is named after its cell
+        let name = match (self.frames.is_empty(), block.span) {
+            (true, _) | (false, None) => String::new(),
+            (false, Some(span)) => {
+                let source = String::from_utf8_lossy(engine_state.get_span_contents(span));
+                let first_line = source.lines().next().unwrap_or_default().trim();
+                first_line.chars().take(40).collect()
+            }
+        };
+        self.frames.push(Frame {
+            name,
+            location: None,
+            locals: Vec::new(),
+        });
+    }
+
+    fn leave_block(&mut self, engine_state: &EngineState, block: &Block) {
+        self.frames.pop();
+        self.cells.pop();
+    }
+
+    fn enter_instruction(
+        &mut self,
+        engine_state: &EngineState,
+        ir_block: &IrBlock,
+        instruction_index: usize,
+        registers: &[PipelineExecutionData],
+    ) {
+        if let Some(Instruction::StoreVariable { var_id, src }) =
+            ir_block.instructions.get(instruction_index) &&
+            let Some(PipelineExecutionData {
+                body: PipelineData::Value(value, _),
+                ..
+            }) = registers.get(src.get() as usize) &&
+            let Some(frame) = self.frames.last_mut()
+        {
+            let span = engine_state.get_var(*var_id).declaration_span;
+            let name = String::from_utf8_lossy(engine_state.get_span_contents(span));
+            let name = name.trim_start_matches('$').to_owned();
+            frame.locals.retain(|(local, _)| *local != name);
+            frame.locals.push((name, value.clone()));
+        }
+
+        let Some(span) = ir_block.spans.get(instruction_index)
+        else {
+            return;
+        };
+        let Some(location) = self.locate(*span)
+        else {
+            return;
+        };
+        let depth = self.frames.len();
+        let Some(frame) = self.frames.last_mut()
+        else {
+            return;
+        };
+
+        // only stop once per line
+        let same_line = frame
+            .location
+            .as_ref()
+            .is_some_and(|previous| previous.same_line(&location));
+        frame.location = Some(location.clone());
+        if same_line {
+            return;
+        }
+
+        let reason = self.session.state.lock().stop_reason(&location, depth);
+        if let Some(reason) = reason {
+            self.session
+                .stop(reason, location, self.frames.clone(), engine_state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn murmur2_matches_ipykernel() {
+        assert_eq!(murmur2(b"", HASH_SEED), 3990065800);
+        assert_eq!(murmur2(b"ls", HASH_SEED), 4073814179);
+        assert_eq!(murmur2(b"let x = 1\n$x + 1", HASH_SEED), 1240504995);
+        assert_eq!(murmur2("ä".as_bytes(), HASH_SEED), 1450432611);
+    }
+
+    fn location(line: usize) -> Location {
+        Location {
+            cell: "cell[1]#1".to_owned(),
+            line,
+            column: 1,
+        }
+    }
+
+    #[test]
+    fn stop_on_breakpoints() {
+        let mut state = SessionState::default();
+        state.cells.insert("cell[1]#1".to_owned(), CellSource {
+            path: "/tmp/1.nu".to_owned(),
+            code: "let x = 1\nlet y = 2".to_owned(),
+        });
+        assert_eq!(state.stop_reason(&location(2), 1), None);
+
+        state
+            .breakpoints
+            .insert("/tmp/1.nu".to_owned(), BTreeSet::from([2]));
+        assert_eq!(state.stop_reason(&location(1), 1), None);
+        assert_eq!(state.stop_reason(&location(2), 1), Some("breakpoint"));
+
+        state.step = Step::Pause;
+        assert_eq!(state.stop_reason(&location(1), 1), Some("pause"));
+    }
+
+    #[test]
+    fn stop_after_steps() {
+        let mut state = SessionState {
+            step: Step::Over {
+                depth: 2,
+                from: location(1),
+            },
+            ..Default::default()
+        };
+        assert_eq!(state.stop_reason(&location(1), 2), None);
+        assert_eq!(state.stop_reason(&location(5), 3), None);
+        assert_eq!(state.stop_reason(&location(2), 2), Some("step"));
+        assert_eq!(state.stop_reason(&location(2), 1), Some("step"));
+
+        state.step = Step::In { from: location(1) };
+        assert_eq!(state.stop_reason(&location(1), 3), None);
+        assert_eq!(state.stop_reason(&location(5), 3), Some("step"));
+
+        state.step = Step::Out { depth: 2 };
+        assert_eq!(state.stop_reason(&location(2), 2), None);
+        assert_eq!(state.stop_reason(&location(2), 1), Some("step"));
+    }
+
+    #[test]
+    fn nested_variables_keep_their_reference() {
+        let record = Value::test_record(nu_protocol::record! {"a" => Value::test_int(1)});
+        let mut stopped = Stopped {
+            frames: vec![],
+            location: location(1),
+            depth: 1,
+            config: Arc::default(),
+            variables: vec![vec![("x".to_owned(), record)]],
+            children: HashMap::new(),
+            globals: 1,
+        };
+        let first = stopped.variables(1);
+        assert_eq!(first, stopped.variables(1));
+        assert_eq!(first[0]["variablesReference"], 2);
+        assert_eq!(stopped.variables.len(), 2);
+        assert_eq!(stopped.variables(2)[0]["name"], "a");
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use mime::Mime;
+use nu_engine::get_full_help;
+use nu_parser::{FlatShape, flatten_block};
+use nu_protocol::engine::{EngineState, Stack, StateWorkingSet};
+use nu_protocol::{PipelineData, Span, Type, Value};
+
+use super::module::KernelInternalSpans;
+use super::render::registry::Renderers;
+use super::render::{FormatDeclIds, PipelineRender, RenderError};
+use super::settings::SharedSettings;
+use crate::util::char_to_byte_offset;
+
+/// Object found under the cursor of an `inspect_request`.
+#[derive(Debug)]
+pub enum Inspection {
+    Command {
+        name: String,
+        /// Full help text as `help <command>` would print it.
+        help: String,
+    },
+    Variable {
+        name: String,
+        ty: Type,
+        /// Current value, `None` if the variable has no value on the stack yet.
+        value: Option<Value>,
+    },
+}
+
+/// Find the command or variable at `cursor_pos` in `code`.
+///
+/// If the cursor is not directly on a command or variable, the closest
+/// command call before the cursor is used, so that inspecting while typing
+/// arguments still shows the help of the called command.
+pub fn inspect(
+    engine_state: &EngineState,
+    stack: &mut Stack,
+    code: &str,
+    cursor_pos: usize,
+) -> Option<Inspection> {
+    let mut working_set = StateWorkingSet::new(engine_state);
+    let offset = working_set.next_span_start();
+    let block = nu_parser::parse(&mut working_set, Some("inspect"), code.as_bytes(), false);
+    let shapes = flatten_block(&working_set, &block);
+    let cursor = offset + char_to_byte_offset(code, cursor_pos);
+
+    let (span, shape) = shapes
+        .iter()
+        .find(|(span, shape)| {
+            span.start <= cursor &&
+                cursor <= span.end &&
+                matches!(shape, FlatShape::InternalCall(_) | FlatShape::Variable(_))
+        })
+        .or_else(|| {
+            shapes.iter().rev().find(|(span, shape)| {
+                span.end <= cursor && matches!(shape, FlatShape::InternalCall(_))
+            })
+        })?;
+
+    match shape {
+        // declarations of this cell are not merged yet, so we cannot show them
+        FlatShape::InternalCall(decl_id) if decl_id.get() < engine_state.num_decls() => {
+            let decl = engine_state.get_decl(*decl_id);
+            let help = get_full_help(decl, engine_state, stack);
+            Some(Inspection::Command {
+                name: decl.name().to_owned(),
+                help,
+            })
+        }
+        FlatShape::Variable(var_id) => {
+            let name = String::from_utf8_lossy(working_set.get_span_contents(*span)).into_owned();
+            let variable = working_set.get_variable(*var_id);
+            let value = stack
+                .get_var(*var_id, Span::unknown())
+                .ok()
+                .or_else(|| variable.const_val.clone());
+            let ty = match value {
+                Some(ref value) => value.get_type(),
+                None => variable.ty.clone(),
+            };
+            Some(Inspection::Variable { name, ty, value })
+        }
+        _ => None,
+    }
+}
+
+impl Inspection {
+    /// Render the inspection as plain text and markdown.
+    pub fn render(
+        self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        spans: &KernelInternalSpans,
+        format_decl_ids: FormatDeclIds,
+        renderers: &Renderers,
+        settings: &SharedSettings,
+    ) -> Result<HashMap<Mime, String>, RenderError> {
+        let md_mime: Mime = "text/markdown"
+            .parse()
+            .expect("'text/markdown' is valid mime type");
+
+        let mut data = HashMap::new();
+        match self {
+            Inspection::Command { name, help } => {
+                let plain_help = nu_utils::strip_ansi_string_likely(help.clone());
+                data.insert(mime::TEXT_PLAIN, help);
+                data.insert(md_mime, format!("### `{name}`\n\n```\n{plain_help}\n```"));
+            }
+            Inspection::Variable {
+                name,
+                ty,
+                value: None,
+            } => {
+                data.insert(mime::TEXT_PLAIN, format!("{name}: {ty}"));
+                data.insert(md_mime, format!("`{name}`: `{ty}`"));
+            }
+            Inspection::Variable {
+                name,
+                ty,
+                value: Some(value),
+            } => {
+                let mut preview = PipelineRender::render(
+                    PipelineData::Value(value, None),
+                    engine_state,
+                    stack,
+                    spans,
+                    format_decl_ids,
+                    renderers,
+                    settings,
+                    Some(md_mime.clone()),
+                )?
+                .data;
+                let mut take_preview = |mime| match preview.remove(mime) {
+                    Some(serde_json::Value::String(preview)) => Some(preview),
+                    _ => None,
+                };
+                let plain_preview = take_preview(&mime::TEXT_PLAIN).unwrap_or_default();
+                data.insert(mime::TEXT_PLAIN, format!("{name}: {ty}\n\n{plain_preview}"));
+                let md_preview = match take_preview(&md_mime) {
+                    Some(md_preview) => md_preview,
+                    None => format!("```\n{plain_preview}\n```"),
+                };
+                data.insert(md_mime, format!("`{name}`: `{ty}`\n\n{md_preview}"));
+            }
+        }
+
+        Ok(data)
+    }
+}
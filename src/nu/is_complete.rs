@@ -0,0 +1,117 @@
+use nu_protocol::ParseError;
+use nu_protocol::engine::{EngineState, StateWorkingSet};
+
+use crate::jupyter::messages::shell::IsCompleteReply;
+
+const INDENT: &str = "    ";
+
+/// Check whether `code` is ready to be executed.
+///
+/// The code is parsed in a throwaway working set, so nothing of it ends up in
+/// the engine state.
+/// Unclosed delimiters and a trailing pipe mark the code as incomplete, any
+/// other parse error marks it as invalid.
+pub fn is_complete(engine_state: &EngineState, code: &str) -> IsCompleteReply {
+    let mut working_set = StateWorkingSet::new(engine_state);
+    nu_parser::parse(&mut working_set, None, code.as_bytes(), false);
+
+    let open_delimiters = open_delimiters(code);
+    let trailing_pipe = code.trim_end().ends_with('|') && !code.trim_end().ends_with("||");
+    let incomplete = || IsCompleteReply::Incomplete {
+        indent: INDENT.repeat(open_delimiters + trailing_pipe as usize),
+    };
+
+    match working_set.parse_errors.first() {
+        Some(ParseError::UnexpectedEof(..) | ParseError::Unclosed(..)) => incomplete(),
+        _ if trailing_pipe => incomplete(),
+        Some(_) => IsCompleteReply::Invalid,
+        None => IsCompleteReply::Complete,
+    }
+}
+
+/// Count the delimiters `{`, `[` and `(` that are not closed at the end of
+/// `code`.
+///
+/// The lexer reports the innermost unclosed delimiter or string, closing it
+/// reveals the next one.
+/// This way strings, comments and bare words like `it's` or `foo#bar` follow
+/// the rules of nu.
+fn open_delimiters(code: &str) -> usize {
+    let mut code = code.to_owned();
+    let mut depth = 0;
+    // every round closes something, escapes at the end may keep strings open
+    for _ in 0..code.len() {
+        let closing = match nu_parser::lex(code.as_bytes(), 0, &[], &[], true) {
+            (_, Some(ParseError::UnexpectedEof(closing, _))) => closing,
+            _ => break,
+        };
+        match closing.as_str() {
+            "}" | "]" | ")" => depth += 1,
+            // unclosed strings don't indent
+            _ if closing.starts_with(['\'', '"', '`']) => (),
+            _ => break,
+        }
+        code.push_str(&closing);
+    }
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(code: &str) -> IsCompleteReply {
+        let engine_state = nu_cmd_lang::create_default_context();
+        is_complete(&engine_state, code)
+    }
+
+    fn indent_of(reply: IsCompleteReply) -> Option<String> {
+        match reply {
+            IsCompleteReply::Incomplete { indent } => Some(indent),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn complete_code() {
+        assert!(matches!(check("let a = 1"), IsCompleteReply::Complete));
+        assert!(matches!(
+            check("if true {\n    1\n}"),
+            IsCompleteReply::Complete
+        ));
+        assert!(matches!(
+            check("'{' | str length"),
+            IsCompleteReply::Complete
+        ));
+    }
+
+    #[test]
+    fn unclosed_delimiters_are_incomplete() {
+        assert_eq!(indent_of(check("if true {")).as_deref(), Some(INDENT));
+        assert_eq!(indent_of(check("[1, (2")), Some(INDENT.repeat(2)));
+        assert_eq!(indent_of(check("{a: [\n")), Some(INDENT.repeat(2)));
+    }
+
+    #[test]
+    fn bare_words_follow_nu_rules() {
+        assert!(matches!(check("echo foo#bar"), IsCompleteReply::Complete));
+        assert!(matches!(check("[1 2] # ["), IsCompleteReply::Complete));
+        assert_eq!(indent_of(check("echo foo#bar {")).as_deref(), Some(INDENT));
+        assert_eq!(indent_of(check("[1 # ]\n")).as_deref(), Some(INDENT));
+        // nu reads the quote of `it's` as the start of a string
+        assert_eq!(indent_of(check("echo it's")).as_deref(), Some(""));
+        assert_eq!(indent_of(check("{ echo it's")).as_deref(), Some(INDENT));
+    }
+
+    #[test]
+    fn trailing_pipe_is_incomplete() {
+        assert_eq!(indent_of(check("[1 2 3] |")).as_deref(), Some(INDENT));
+        assert_eq!(indent_of(check("[1 2 3] |  \n")).as_deref(), Some(INDENT));
+    }
+
+    #[test]
+    fn parse_errors_are_invalid() {
+        assert!(matches!(check("let = 3"), IsCompleteReply::Invalid));
+        assert!(matches!(check("1 + }"), IsCompleteReply::Invalid));
+    }
+}
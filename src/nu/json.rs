@@ -0,0 +1,135 @@
+use nu_protocol::{Record, ShellError, Signals, Span, Value};
+use serde_json::{Map, Number};
+
+/// Convert a nu value structurally into a JSON value.
+///
+/// Filesizes are converted into bytes, durations into nanoseconds and dates
+/// into RFC 3339 strings, similar to `to json`.
+/// Ranges are lazy, so they are only expanded up to `max_rows` items, the
+/// limit of rendered tables, and stop on interrupts.
+pub fn value_to_json(
+    value: &Value,
+    signals: &Signals,
+    max_rows: usize,
+) -> Result<serde_json::Value, ShellError> {
+    let to_json = |value: &Value| value_to_json(value, signals, max_rows);
+    let span = value.span();
+    Ok(match value {
+        Value::Nothing { .. } => serde_json::Value::Null,
+        Value::Bool { val, .. } => serde_json::Value::Bool(*val),
+        Value::Int { val, .. } => serde_json::Value::Number((*val).into()),
+        Value::Float { val, .. } => match Number::from_f64(*val) {
+            Some(number) => serde_json::Value::Number(number),
+            None => serde_json::Value::Null,
+        },
+        Value::Filesize { val, .. } => serde_json::Value::Number(val.get().into()),
+        Value::Duration { val, .. } => serde_json::Value::Number((*val).into()),
+        Value::Date { val, .. } => serde_json::Value::String(val.to_rfc3339()),
+        Value::String { val, .. } | Value::Glob { val, .. } => {
+            serde_json::Value::String(val.clone())
+        }
+        Value::CellPath { val, .. } => serde_json::Value::String(val.to_string()),
+        Value::Binary { val, .. } => serde_json::Value::Array(
+            val.iter()
+                .map(|byte| serde_json::Value::Number((*byte).into()))
+                .collect(),
+        ),
+        Value::Range { val, .. } if val.is_bounded() => {
+            let vals = val
+                .into_range_iter(span, signals.clone())
+                .take(max_rows.saturating_add(1))
+                .map(|value| to_json(&value))
+                .collect::<Result<Vec<_>, _>>()?;
+            signals.check(&span)?;
+            if vals.len() > max_rows {
+                return Err(ShellError::UnsupportedInput {
+                    msg: format!(
+                        "ranges with more than {max_rows} items are not converted into json"
+                    ),
+                    input: "value originates from here".to_string(),
+                    msg_span: span,
+                    input_span: span,
+                });
+            }
+            serde_json::Value::Array(vals)
+        }
+        Value::Range { .. } => {
+            return Err(ShellError::UnsupportedInput {
+                msg: "unbounded ranges cannot be converted into json".to_string(),
+                input: "value originates from here".to_string(),
+                msg_span: span,
+                input_span: span,
+            });
+        }
+        Value::List { vals, .. } => {
+            serde_json::Value::Array(vals.iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+        Value::Record { val, .. } => serde_json::Value::Object(
+            val.iter()
+                .map(|(k, v)| Ok((k.clone(), to_json(v)?)))
+                .collect::<Result<Map<_, _>, ShellError>>()?,
+        ),
+        Value::Custom { val, .. } => to_json(&val.to_base_value(span)?)?,
+        Value::Error { error, .. } => return Err(*error.clone()),
+        Value::Closure { .. } => {
+            return Err(ShellError::UnsupportedInput {
+                msg: "closures cannot be converted into json".to_string(),
+                input: "value originates from here".to_string(),
+                msg_span: span,
+                input_span: span,
+            });
+        }
+    })
+}
+
+/// Convert a JSON value into a nu value.
+pub fn json_to_value(json: serde_json::Value, span: Span) -> Value {
+    match json {
+        serde_json::Value::Null => Value::nothing(span),
+        serde_json::Value::Bool(val) => Value::bool(val, span),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(val) => Value::int(val, span),
+            None => Value::float(number.as_f64().unwrap_or(f64::NAN), span),
+        },
+        serde_json::Value::String(val) => Value::string(val, span),
+        serde_json::Value::Array(vals) => Value::list(
+            vals.into_iter()
+                .map(|json| json_to_value(json, span))
+                .collect(),
+            span,
+        ),
+        serde_json::Value::Object(map) => Value::record(
+            map.into_iter()
+                .map(|(k, json)| (k, json_to_value(json, span)))
+                .collect::<Record>(),
+            span,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nu_protocol::ast::RangeInclusion;
+    use nu_protocol::{IntRange, Range};
+
+    use super::*;
+
+    #[test]
+    fn ranges_are_limited() {
+        let span = Span::unknown();
+        let range = |end| {
+            let range = IntRange::new(
+                Value::int(1, span),
+                Value::nothing(span),
+                Value::int(end, span),
+                RangeInclusion::Inclusive,
+                span,
+            )
+            .unwrap();
+            Value::range(Range::IntRange(range), span)
+        };
+        let json = value_to_json(&range(3), &Signals::empty(), 3).unwrap();
+        assert_eq!(json, serde_json::json!([1, 2, 3]));
+        assert!(value_to_json(&range(1_000_000_000), &Signals::empty(), 3).is_err());
+    }
+}
@@ -3,6 +3,7 @@ use nu_protocol::engine::{EngineState, Stack, StateWorkingSet};
 use nu_protocol::{FromValue, IntoValue, ShellError, Span, Type, VarId};
 
 use crate::CARGO_TOML;
+use crate::jupyter::messages::shell::ExecuteRequest;
 use crate::jupyter::messages::{Header, Message};
 
 #[derive(Debug, Clone, Copy)]
@@ -29,19 +30,42 @@ impl Konst {
         self.var_id
     }
 
-    pub fn update<C>(&self, stack: &mut Stack, cell_name: String, message: Message<C>) {
-        let data = KonstData {
-            version: KonstDataVersion {
-                kernel: CARGO_TOML.package.version.to_owned(),
-                nu: CARGO_TOML.dependencies.nu_engine.version.to_owned(),
-            },
-            cell: cell_name,
-            message: KonstDataMessage {
-                zmq_identities: message.zmq_identities,
-                header: message.header,
-                parent_header: message.parent_header,
-            },
+    pub fn update<C>(
+        &self,
+        stack: &mut Stack,
+        cell_name: String,
+        message: Message<C>,
+        request: &ExecuteRequest,
+        subshell_id: Option<String>,
+    ) {
+        let execute = KonstDataExecute {
+            allow_stdin: request.allow_stdin,
+            silent: request.silent,
         };
+        let data = KonstData::new(cell_name, message, execute, subshell_id);
+        stack.add_var(self.var_id, data.into_value(Span::unknown()))
+    }
+
+    /// Update the message for nu code running outside of an
+    /// `execute_request`, e.g. for comm handlers.
+    ///
+    /// Only the cell name of the last executed cell is kept, as no frontend
+    /// waits for input of such code and its output is always shown.
+    pub fn update_message<C>(
+        &self,
+        stack: &mut Stack,
+        message: Message<C>,
+        subshell_id: Option<String>,
+    ) {
+        let cell = self
+            .data(stack, Span::unknown())
+            .map(|data| data.cell)
+            .unwrap_or_default();
+        let execute = KonstDataExecute {
+            allow_stdin: false,
+            silent: false,
+        };
+        let data = KonstData::new(cell, message, execute, subshell_id);
         stack.add_var(self.var_id, data.into_value(Span::unknown()))
     }
 
@@ -58,6 +82,29 @@ pub struct KonstData {
     pub version: KonstDataVersion,
     pub cell: String,
     pub message: KonstDataMessage,
+    pub execute: KonstDataExecute,
+    /// Subshell running the code, `None` for the main shell.
+    pub subshell_id: Option<String>,
+}
+
+impl KonstData {
+    fn new<C>(
+        cell: String,
+        message: Message<C>,
+        execute: KonstDataExecute,
+        subshell_id: Option<String>,
+    ) -> Self {
+        KonstData {
+            version: KonstDataVersion {
+                kernel: CARGO_TOML.package.version.to_owned(),
+                nu: CARGO_TOML.dependencies.nu_engine.version.to_owned(),
+            },
+            cell,
+            message: message.into(),
+            execute,
+            subshell_id,
+        }
+    }
 }
 
 #[derive(Debug, Clone, IntoValue, FromValue)]
@@ -72,3 +119,20 @@ pub struct KonstDataMessage {
     pub header: Header,
     pub parent_header: Option<Header>,
 }
+
+impl<C> From<Message<C>> for KonstDataMessage {
+    fn from(message: Message<C>) -> Self {
+        KonstDataMessage {
+            zmq_identities: message.zmq_identities,
+            header: message.header,
+            parent_header: message.parent_header,
+        }
+    }
+}
+
+/// Flags of the `execute_request` of the current cell.
+#[derive(Debug, Clone, IntoValue, FromValue)]
+pub struct KonstDataExecute {
+    pub allow_stdin: bool,
+    pub silent: bool,
+}
@@ -6,19 +6,23 @@ use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 
 use miette::{MietteHandlerOpts, NarratableReportHandler, ReportHandler, RgbColors};
-use nu_protocol::ast::{PipelineRedirection, RedirectionTarget};
-use nu_protocol::debugger::WithoutDebug;
-use nu_protocol::engine::{EngineState, Stack, StateDelta, StateWorkingSet};
+use nu_protocol::engine::{EngineState, Redirection, Stack, StateDelta, StateWorkingSet};
 use nu_protocol::{
-    CompileError, ErrorStyle, NU_VARIABLE_ID, ParseError, PipelineData, ShellError,
+    CompileError, ErrorStyle, NU_VARIABLE_ID, OutDest, ParseError, PipelineData, ShellError,
     ShortReportHandler, Signals, Span, Value,
 };
 use thiserror::Error;
 
 pub mod commands;
+pub mod completion;
+pub mod debugger;
+pub mod inspect;
+pub mod is_complete;
+pub mod json;
 pub mod konst;
 pub mod module;
 pub mod render;
+pub mod settings;
 
 #[allow(clippy::let_and_return)] // i like it here
 pub fn initial_engine_state() -> EngineState {
@@ -116,7 +120,7 @@ pub fn execute(
 ) -> Result<PipelineData, ExecuteError> {
     let code = code.as_bytes();
     let mut working_set = StateWorkingSet::new(engine_state);
-    let mut block = nu_parser::parse(&mut working_set, Some(name), code, false);
+    let block = nu_parser::parse(&mut working_set, Some(name), code, false);
 
     // TODO: report parse warnings
 
@@ -134,26 +138,13 @@ pub fn execute(
         });
     }
 
-    match Arc::get_mut(&mut block) {
-        None => todo!(),
-        Some(block) => {
-            if let Some(last_pipeline) = block.pipelines.last_mut()
-                && let Some(last_pipeline_element) = last_pipeline.elements.last_mut() {
-                    last_pipeline_element.redirection = Some(PipelineRedirection::Separate {
-                        out: RedirectionTarget::Pipe {
-                            span: Span::unknown(),
-                        },
-                        err: RedirectionTarget::Pipe {
-                            span: Span::unknown(),
-                        },
-                    })
-                }
-        }
-    }
-
     engine_state.merge_delta(working_set.delta)?;
-    let res =
-        nu_engine::eval_block::<WithoutDebug>(engine_state, stack, &block, PipelineData::Empty)?;
+    // evaluates with the debugger if a debug session is attached
+    let eval_block = nu_engine::get_eval_block(engine_state);
+    // the stdout of a trailing external is piped back to be streamed while
+    // rendering, its stderr goes directly into the stderr of the stack
+    let mut stack = stack.push_redirection(Some(Redirection::Pipe(OutDest::PipeSeparate)), None);
+    let res = eval_block(engine_state, &mut stack, &block, PipelineData::Empty)?;
     Ok(res.body)
 }
 
@@ -283,6 +274,11 @@ impl<'s> ReportExecuteError<'s> {
     pub fn fmt(&self) -> String {
         format!("Error: {self:?}")
     }
+
+    /// The rendered report split into lines, as Jupyter expects a traceback.
+    pub fn traceback(&self) -> Vec<String> {
+        self.fmt().lines().map(ToOwned::to_owned).collect()
+    }
 }
 
 impl<'s> miette::Diagnostic for ReportExecuteError<'s> {
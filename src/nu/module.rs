@@ -12,9 +12,6 @@ const MODULE: &str = indoc! {r#"
             @kernel-internal
             def "csv" []: any -> string {...}
 
-            @kernel-internal
-            def "json" []: any -> string {...}
-
             @kernel-internal
             def "html" []: any -> string {...}
 
@@ -36,7 +33,6 @@ pub struct KernelInternalSpans {
 pub struct KernelInternalRenderSpans {
     pub text: Span,
     pub csv: Span,
-    pub json: Span,
     pub html: Span,
     pub md: Span,
     pub svg: Span,
@@ -56,7 +52,6 @@ pub fn create_nuju_module(engine_state: &mut EngineState) -> KernelInternalSpans
 
     let (_, render_text_span) = find_decl(render_block, "text").expect("find render/text decl");
     let (_, render_csv_span) = find_decl(render_block, "csv").expect("find render/csv decl");
-    let (_, render_json_span) = find_decl(render_block, "json").expect("find render/json decl");
     let (_, render_html_span) = find_decl(render_block, "html").expect("find render/html decl");
     let (_, render_md_span) = find_decl(render_block, "md").expect("find render/md decl");
     let (_, render_svg_span) = find_decl(render_block, "svg").expect("find render/svg decl");
@@ -69,7 +64,6 @@ pub fn create_nuju_module(engine_state: &mut EngineState) -> KernelInternalSpans
         render: KernelInternalRenderSpans {
             text: render_text_span,
             csv: render_csv_span,
-            json: render_json_span,
             html: render_html_span,
             md: render_md_span,
             svg: render_svg_span,
@@ -123,8 +117,8 @@ mod tests {
         let mut engine_state = nu_cmd_lang::create_default_context();
         let spans = create_nuju_module(&mut engine_state);
         assert_eq!(
-            engine_state.get_span_contents(spans.render.json),
-            br#"def "json" []: any -> string {...}"#
+            engine_state.get_span_contents(spans.render.csv),
+            br#"def "csv" []: any -> string {...}"#
         )
     }
 }
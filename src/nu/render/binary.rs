@@ -0,0 +1,202 @@
+//! Rendering of binary data like images, documents and audio.
+//!
+//! The media type is taken from the `content_type` of the pipeline metadata or
+//! guessed from the magic bytes at the start of the data.
+//! Strings are only rendered as SVG images with a content type, other strings
+//! that happen to contain SVG stay text.
+
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use mime::Mime;
+use nu_protocol::Value;
+use serde_json::json;
+
+use super::PipelineRender;
+
+/// How much of a string is searched for an `<svg` tag.
+const SVG_SNIFF_LEN: usize = 1024;
+
+/// Render binary data that frontends can display directly.
+///
+/// Returns `None` if the value is no such data.
+pub fn render(value: &Value, content_type: Option<&str>) -> Option<PipelineRender> {
+    let content_type = content_type
+        .and_then(|content_type| content_type.parse::<Mime>().ok())
+        .filter(is_displayable);
+
+    let (mime, bytes) = match value {
+        Value::Binary { val, .. } => (content_type.or_else(|| sniff(val))?, val.as_slice()),
+        Value::String { val, .. } => match content_type? {
+            // only svg images are text
+            mime if mime == mime::IMAGE_SVG => (mime, val.as_bytes()),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let dimensions = dimensions(&mime, bytes);
+    let description = match dimensions {
+        Some((width, height)) => format!("<{mime} {width}x{height}, {} bytes>", bytes.len()),
+        None => format!("<{mime}, {} bytes>", bytes.len()),
+    };
+
+    let mut data = HashMap::from([(mime::TEXT_PLAIN, description.into())]);
+    let mut metadata = HashMap::new();
+    let encoded = match mime == mime::IMAGE_SVG {
+        true => String::from_utf8_lossy(bytes).into_owned(),
+        false => BASE64.encode(bytes),
+    };
+    if mime.type_() == mime::AUDIO {
+        // frontends have no audio renderer, so an audio element is added
+        let html = format!(r#"<audio controls src="data:{mime};base64,{encoded}"></audio>"#);
+        data.insert(mime::TEXT_HTML, html.into());
+    }
+    if let Some((width, height)) = dimensions {
+        metadata.insert(mime.clone(), json!({"width": width, "height": height}));
+    }
+    data.insert(mime, encoded.into());
+
+    Some(PipelineRender { data, metadata })
+}
+
+/// Whether frontends can display data of this media type.
+fn is_displayable(mime: &Mime) -> bool {
+    matches!(
+        (mime.type_(), mime.subtype().as_str()),
+        (mime::IMAGE, "png" | "jpeg" | "gif" | "webp" | "svg") |
+            (mime::APPLICATION, "pdf") |
+            (mime::AUDIO, _)
+    )
+}
+
+/// Guess the media type from the magic bytes at the start of the data.
+fn sniff(bytes: &[u8]) -> Option<Mime> {
+    let mime = match bytes {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', ..] if bytes.get(8..12) == Some(b"WEBP") => "image/webp",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "application/pdf",
+        [b'R', b'I', b'F', b'F', ..] if bytes.get(8..12) == Some(b"WAVE") => "audio/wav",
+        [b'O', b'g', b'g', b'S', ..] => "audio/ogg",
+        [b'f', b'L', b'a', b'C', ..] => "audio/flac",
+        [b'I', b'D', b'3', ..] => "audio/mpeg",
+        _ if is_mp3_frame(bytes) => "audio/mpeg",
+        _ if is_svg(bytes) => "image/svg+xml",
+        _ => return None,
+    };
+    Some(mime.parse().expect("sniffed mime types are valid"))
+}
+
+/// Whether the data starts with the header of an MPEG audio layer III frame.
+fn is_mp3_frame(bytes: &[u8]) -> bool {
+    let [0xFF, version_layer, rates, mode, ..] = *bytes
+    else {
+        return false;
+    };
+    // sync bits, no reserved version and layer III
+    version_layer & 0xE0 == 0xE0 &&
+        version_layer & 0x18 != 0x08 &&
+        version_layer & 0x06 == 0x02 &&
+        // a known bitrate and sample rate, no reserved emphasis
+        !matches!(rates >> 4, 0x0 | 0xF) &&
+        rates & 0x0C != 0x0C &&
+        mode & 0x03 != 0x02
+}
+
+fn is_svg(bytes: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(&bytes[..bytes.len().min(SVG_SNIFF_LEN)]);
+    let start = start.trim_start();
+    start.starts_with("<svg") || (start.starts_with("<?xml") && start.contains("<svg"))
+}
+
+/// Width and height of an image, read from its header.
+fn dimensions(mime: &Mime, bytes: &[u8]) -> Option<(u32, u32)> {
+    let be_u16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let le_u16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let be_u32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le_u32 = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le_u24 = |at: usize| Some(le_u32(at)? & 0xFF_FFFF);
+
+    match mime.subtype().as_str() {
+        "png" if bytes.get(12..16) == Some(b"IHDR") => Some((be_u32(16)?, be_u32(20)?)),
+        "gif" => Some((le_u16(6)?.into(), le_u16(8)?.into())),
+        "webp" => match bytes.get(12..16)? {
+            b"VP8 " => Some(((le_u16(26)? & 0x3FFF).into(), (le_u16(28)? & 0x3FFF).into())),
+            b"VP8L" => {
+                let bits = le_u32(21)?;
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8X" => Some((le_u24(24)? + 1, le_u24(27)? + 1)),
+            _ => None,
+        },
+        "jpeg" => {
+            // walk the segments until a start of frame
+            let mut at = 2;
+            loop {
+                if *bytes.get(at)? != 0xFF {
+                    return None;
+                }
+                match *bytes.get(at + 1)? {
+                    0xFF => at += 1,
+                    0x01 | 0xD0..=0xD7 => at += 2,
+                    0xC0..=0xCF if !matches!(bytes[at + 1], 0xC4 | 0xC8 | 0xCC) => {
+                        return Some((be_u16(at + 7)?.into(), be_u16(at + 5)?.into()));
+                    }
+                    _ => at += 2 + usize::from(be_u16(at + 2)?),
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_magic_bytes() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x20\0\0\0\x10";
+        assert_eq!(sniff(png), Some(mime::IMAGE_PNG));
+        assert_eq!(dimensions(&mime::IMAGE_PNG, png), Some((32, 16)));
+
+        let gif = b"GIF89a\x20\0\x10\0";
+        assert_eq!(sniff(gif), Some(mime::IMAGE_GIF));
+        assert_eq!(dimensions(&mime::IMAGE_GIF, gif), Some((32, 16)));
+
+        let jpeg = b"\xFF\xD8\xFF\xE0\0\x04ab\xFF\xC0\0\x11\x08\0\x10\0\x20";
+        assert_eq!(sniff(jpeg), Some(mime::IMAGE_JPEG));
+        assert_eq!(dimensions(&mime::IMAGE_JPEG, jpeg), Some((32, 16)));
+
+        assert_eq!(sniff(b"  <svg></svg>"), Some(mime::IMAGE_SVG));
+        assert_eq!(sniff(b"%PDF-1.7"), Some(mime::APPLICATION_PDF));
+        assert_eq!(sniff(b"hello"), None);
+    }
+
+    #[test]
+    fn sniff_mp3() {
+        let mpeg: Mime = "audio/mpeg".parse().unwrap();
+        assert_eq!(sniff(b"ID3\x04\0"), Some(mpeg.clone()));
+        assert_eq!(sniff(b"\xFF\xFB\x90\x64"), Some(mpeg));
+        // free and invalid bitrates, reserved sample rate
+        assert_eq!(sniff(b"\xFF\xFB\x00\x64"), None);
+        assert_eq!(sniff(b"\xFF\xFB\xF0\x64"), None);
+        assert_eq!(sniff(b"\xFF\xF3\x9C\x64"), None);
+        assert_eq!(sniff(b"\xFF\xF2"), None);
+    }
+
+    #[test]
+    fn svg_strings_need_a_content_type() {
+        let svg = Value::test_string("<svg></svg>");
+        assert!(render(&svg, None).is_none());
+
+        let rendered = render(&svg, Some("image/svg+xml")).unwrap();
+        assert_eq!(rendered.data[&mime::IMAGE_SVG], "<svg></svg>");
+
+        let binary = Value::test_binary(b"<svg></svg>".to_vec());
+        assert!(render(&binary, None).is_some());
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 
 use mime::Mime;
-use nu_command::{ToCsv, ToJson, ToMd};
+use nu_command::{ToCsv, ToMd};
 use nu_plotters::commands::draw::DrawSvg;
 use nu_protocol::ast::{Argument, Call};
 use nu_protocol::debugger::WithoutDebug;
@@ -9,9 +10,18 @@ use nu_protocol::engine::{Command, EngineState, Stack};
 use nu_protocol::{DeclId, PipelineData, ShellError, Span, Spanned, Value};
 use thiserror::Error;
 
+use self::registry::Renderers;
+use super::json::value_to_json;
 use super::module::KernelInternalSpans;
+use super::settings::SharedSettings;
 use crate::error::KernelError;
 
+mod binary;
+pub mod registry;
+mod table;
+
+pub use self::table::TableLimits;
+
 macro_rules! create_format_decl_ids {
     ($($field:ident : $search_str:expr),+ $(,)?) => {
         #[derive(Debug, Clone, Copy)]
@@ -48,7 +58,6 @@ macro_rules! create_format_decl_ids {
 create_format_decl_ids!(
     to_text: "to text",
     to_csv: "to csv",
-    to_json: "to json",
     to_html: "to html",
     to_md: "to md",
     table: "table",
@@ -74,6 +83,24 @@ pub enum RenderError {
 
     #[error("could not render plain text output: {0}")]
     NoText(#[source] ShellError),
+
+    #[error("could not render {mime} via registered renderer: {source}")]
+    Renderer {
+        mime: Mime,
+        #[source]
+        source: ShellError,
+    },
+}
+
+impl From<RenderError> for ShellError {
+    fn from(err: RenderError) -> Self {
+        // the inner error points at the value that failed, e.g. a failing stream
+        match err {
+            RenderError::IntoValue(err) |
+            RenderError::NoText(err) |
+            RenderError::Renderer { source: err, .. } => err,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -85,8 +112,8 @@ enum InternalRenderError {
 
 #[derive(Debug)]
 pub struct PipelineRender {
-    pub data: HashMap<Mime, String>,
-    pub metadata: HashMap<Mime, String>,
+    pub data: HashMap<Mime, serde_json::Value>,
+    pub metadata: HashMap<Mime, serde_json::Value>,
 }
 
 impl PipelineRender {
@@ -141,59 +168,96 @@ impl PipelineRender {
         Ok(formatted)
     }
 
+    #[allow(clippy::too_many_arguments)] // everything the user may customize
     pub fn render(
         pipeline_data: PipelineData,
         engine_state: &EngineState,
         stack: &mut Stack,
         spans: &KernelInternalSpans,
         format_decl_ids: FormatDeclIds,
+        renderers: &Renderers,
+        settings: &SharedSettings,
         filter: Option<Mime>,
     ) -> Result<PipelineRender, RenderError> {
-        let mut data = HashMap::new();
         let metadata = HashMap::new();
+        let content_type = pipeline_data
+            .metadata()
+            .and_then(|metadata| metadata.content_type);
         let value = pipeline_data
             .into_value(Span::unknown())
             .map_err(RenderError::IntoValue)?;
         let ty = value.get_type();
 
-        // `to text` has any input type, no need to check
-        // also we always need to provide plain text output
-        match Self::render_via_call(
-            value.clone(),
-            format_decl_ids.to_text,
-            engine_state,
-            stack,
-            spans.render.text,
-            vec![],
-        ) {
-            Ok(s) => data.insert(mime::TEXT_PLAIN, s),
-            Err(
-                InternalRenderError::Eval(e) |
-                InternalRenderError::IntoValue(e) |
-                InternalRenderError::NoString(e),
-            ) => return Err(RenderError::NoText(e)),
-        };
+        let match_filter = |mime: &Mime| filter.is_none() || filter.as_ref() == Some(mime);
 
-        let match_filter = |mime| filter.is_none() || filter == Some(mime);
+        let config = stack.get_config(engine_state);
+        let limits = TableLimits::new(&settings.lock().table, &config);
 
-        // call directly as `ToHtml` is private
-        if match_filter(mime::TEXT_HTML) {
-            let span = spans.render.html;
+        let mut data = renderers.render(&value, engine_state, stack, limits.max_rows, |mime| {
+            *mime == mime::TEXT_PLAIN || match_filter(mime)
+        });
+        // registered renderers override the built-in ones
+        let built_in =
+            |data: &HashMap<Mime, _>, mime: &Mime| match_filter(mime) && !data.contains_key(mime);
+
+        if let Some(mut render) = binary::render(&value, content_type.as_deref()) {
+            render
+                .data
+                .retain(|mime, _| *mime == mime::TEXT_PLAIN || match_filter(mime));
+            render.data.extend(data);
+            return Ok(render);
+        }
+
+        // large lists and records are cut down to the limits of the html table,
+        // heavy formats without elision are left out to keep the output small
+        let elided = table::elide(&value, limits);
+        let heavy = |mime: &Mime| elided.is_none() || *mime == mime::TEXT_HTML;
+        let built_in = |data: &HashMap<Mime, _>, mime: &Mime| built_in(data, mime) && heavy(mime);
+
+        // `to text` has any input type, no need to check
+        // also we always need to provide plain text output
+        if let Entry::Vacant(entry) = data.entry(mime::TEXT_PLAIN) {
             match Self::render_via_call(
-                value.clone(),
-                format_decl_ids.to_html,
+                elided.clone().unwrap_or_else(|| value.clone()),
+                format_decl_ids.to_text,
                 engine_state,
                 stack,
-                span,
-                vec![flag("partial", span), flag("html-color", span)],
+                spans.render.text,
+                vec![],
             ) {
-                Ok(s) => data.insert(mime::TEXT_HTML, s),
-                Err(InternalRenderError::Eval(_)) => None,
-                Err(_) => None, // TODO: print the error
+                Ok(s) => entry.insert(s.into()),
+                Err(
+                    InternalRenderError::Eval(e) |
+                    InternalRenderError::IntoValue(e) |
+                    InternalRenderError::NoString(e),
+                ) => return Err(RenderError::NoText(e)),
             };
         }
 
-        if match_filter(mime::TEXT_CSV) {
+        // call directly as `ToHtml` is private
+        if built_in(&data, &mime::TEXT_HTML) {
+            let span = spans.render.html;
+            // lists and records get a table with limits, `to html` writes every row
+            if let Some(html) = table::render(&value, limits, &config) {
+                data.insert(mime::TEXT_HTML, html.into());
+            }
+            else {
+                match Self::render_via_call(
+                    value.clone(),
+                    format_decl_ids.to_html,
+                    engine_state,
+                    stack,
+                    span,
+                    vec![flag("partial", span), flag("html-color", span)],
+                ) {
+                    Ok(s) => data.insert(mime::TEXT_HTML, s.into()),
+                    Err(InternalRenderError::Eval(_)) => None,
+                    Err(_) => None, // TODO: print the error
+                };
+            }
+        }
+
+        if built_in(&data, &mime::TEXT_CSV) {
             match Self::render_via_cmd(
                 &value,
                 ToCsv,
@@ -202,31 +266,23 @@ impl PipelineRender {
                 spans.render.csv,
                 stack,
             ) {
-                Ok(Some(s)) => data.insert(mime::TEXT_CSV, s),
+                Ok(Some(s)) => data.insert(mime::TEXT_CSV, s.into()),
                 Ok(None) | Err(InternalRenderError::Eval(_)) => None,
                 Err(_) => None, // TODO: print the error
             };
         }
 
-        if match_filter(mime::APPLICATION_JSON) {
-            match Self::render_via_cmd(
-                &value,
-                ToJson,
-                format_decl_ids.to_json,
-                engine_state,
-                spans.render.json,
-                stack,
-            ) {
-                Ok(Some(s)) => data.insert(mime::APPLICATION_JSON, s),
-                Ok(None) | Err(InternalRenderError::Eval(_)) => None,
-                Err(_) => None, // TODO: print the error
-            };
+        if built_in(&data, &mime::APPLICATION_JSON) {
+            // values that cannot be represented in json, like closures, are skipped
+            if let Ok(json) = value_to_json(&value, engine_state.signals(), limits.max_rows) {
+                data.insert(mime::APPLICATION_JSON, json);
+            }
         }
 
         let md_mime: mime::Mime = "text/markdown"
             .parse()
             .expect("'text/markdown' is valid mime type");
-        if match_filter(md_mime.clone()) {
+        if built_in(&data, &md_mime) {
             match Self::render_via_cmd(
                 &value,
                 ToMd,
@@ -235,14 +291,14 @@ impl PipelineRender {
                 spans.render.md,
                 stack,
             ) {
-                Ok(Some(s)) => data.insert(md_mime, s),
+                Ok(Some(s)) => data.insert(md_mime, s.into()),
                 Ok(None) | Err(InternalRenderError::Eval(_)) => None,
                 Err(_) => None, // TODO: print the error
             };
         }
 
         // TODO: feature flag this
-        if match_filter(mime::IMAGE_SVG) {
+        if built_in(&data, &mime::IMAGE_SVG) {
             match Self::render_via_cmd(
                 &value,
                 DrawSvg,
@@ -251,20 +307,41 @@ impl PipelineRender {
                 spans.render.svg,
                 stack,
             ) {
-                Ok(Some(s)) => data.insert(mime::IMAGE_SVG, s),
+                Ok(Some(s)) => data.insert(mime::IMAGE_SVG, s.into()),
                 Ok(None) | Err(InternalRenderError::Eval(_)) => None,
                 Err(_) => None, // TODO: print the error
             };
         }
 
+        // json media types like vega specs are passed on as they are
+        if let Some(mime) = content_type.and_then(|content_type| content_type.parse::<Mime>().ok()) &&
+            is_json(&mime) &&
+            mime != mime::APPLICATION_JSON &&
+            built_in(&data, &mime)
+        {
+            let json = match &value {
+                Value::String { val, .. } => serde_json::from_str(val).ok(),
+                value => value_to_json(value, engine_state.signals(), limits.max_rows).ok(),
+            };
+            if let Some(json) = json {
+                data.insert(mime, json);
+            }
+        }
+
         Ok(PipelineRender { data, metadata })
     }
 }
 
+/// Whether data of this media type is json, e.g.
+/// `application/vnd.vegalite.v5+json`.
+fn is_json(mime: &Mime) -> bool {
+    mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON)
+}
+
 #[derive(Debug)]
 pub struct StringifiedPipelineRender {
-    pub data: HashMap<String, String>,
-    pub metadata: HashMap<String, String>,
+    pub data: HashMap<String, serde_json::Value>,
+    pub metadata: HashMap<String, serde_json::Value>,
 }
 
 impl From<PipelineRender> for StringifiedPipelineRender {
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use mime::Mime;
+use nu_engine::ClosureEvalOnce;
+use nu_protocol::engine::{Closure, EngineState, Stack};
+use nu_protocol::{OutDest, ShellError, Value};
+use parking_lot::Mutex;
+
+use super::RenderError;
+use crate::nu::json::value_to_json;
+
+pub type SharedRendererRegistry = Arc<Mutex<RendererRegistry>>;
+
+/// Renderers registered via `nuju render register` by every shell.
+///
+/// Closures only exist in the engine state of the shell that registered them,
+/// so every shell only uses its own renderers.
+/// Subshells start with the renderers of the shell they are created from, as
+/// they also start from its engine state.
+#[derive(Debug, Default)]
+pub struct RendererRegistry {
+    main: Renderers,
+    subshells: HashMap<String, Renderers>,
+}
+
+/// Renderers of a single shell.
+///
+/// Registered renderers take precedence over the built-in ones for their mime
+/// type.
+#[derive(Debug, Default, Clone)]
+pub struct Renderers {
+    renderers: HashMap<Mime, Renderer>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    closure: Closure,
+    /// Only values this closure returns `true` for are rendered.
+    when: Option<Closure>,
+    /// Failures are only reported once, renderers run for every output.
+    reported: Arc<AtomicBool>,
+}
+
+impl RendererRegistry {
+    /// Renderers of a shell, `None` for the main shell.
+    ///
+    /// Cloned, so that renderers may register other renderers.
+    pub fn shell(&self, subshell_id: Option<&str>) -> Renderers {
+        match subshell_id {
+            None => self.main.clone(),
+            Some(id) => self.subshells.get(id).cloned().unwrap_or_default(),
+        }
+    }
+
+    pub fn shell_mut(&mut self, subshell_id: Option<&str>) -> &mut Renderers {
+        match subshell_id {
+            None => &mut self.main,
+            Some(id) => self.subshells.entry(id.to_owned()).or_default(),
+        }
+    }
+
+    /// Set the renderers a new subshell starts with.
+    pub fn insert_subshell(&mut self, subshell_id: String, renderers: Renderers) {
+        self.subshells.insert(subshell_id, renderers);
+    }
+
+    /// Drop the renderers of a deleted subshell.
+    pub fn remove_subshell(&mut self, subshell_id: &str) {
+        self.subshells.remove(subshell_id);
+    }
+}
+
+impl Renderers {
+    /// Register a renderer, replacing a previous one for the same mime type.
+    pub fn register(&mut self, mime: Mime, renderer: Renderer) {
+        self.renderers.insert(mime, renderer);
+    }
+
+    /// Remove the renderer of a mime type, the built-in one is used again.
+    pub fn unregister(&mut self, mime: &Mime) {
+        self.renderers.remove(mime);
+    }
+
+    /// Render a value with every registered renderer that accepts it.
+    ///
+    /// Failing renderers are skipped, so that one broken renderer doesn't
+    /// break every output.
+    pub fn render(
+        &self,
+        value: &Value,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        max_rows: usize,
+        filter: impl Fn(&Mime) -> bool,
+    ) -> HashMap<Mime, serde_json::Value> {
+        let mut data = HashMap::new();
+        for (mime, renderer) in self.renderers.iter().filter(|(mime, _)| filter(mime)) {
+            match renderer.render(value, engine_state, stack, max_rows) {
+                Ok(Some(rendered)) => _ = data.insert(mime.clone(), rendered),
+                Ok(None) => (),
+                Err(source) => {
+                    if !renderer.reported.swap(true, Ordering::Relaxed) {
+                        let err = RenderError::Renderer {
+                            mime: mime.clone(),
+                            source,
+                        };
+                        report(stack, &err);
+                    }
+                }
+            }
+        }
+        data
+    }
+}
+
+/// Report an error on the stderr of the cell, the kernel's stderr is only the
+/// fallback.
+fn report(stack: &Stack, err: &RenderError) {
+    if let OutDest::File(file) = stack.stderr() &&
+        writeln!(file.as_ref(), "{err}").is_ok()
+    {
+        return;
+    }
+    eprintln!("{err}");
+}
+
+impl Renderer {
+    pub fn new(closure: Closure, when: Option<Closure>) -> Self {
+        Self {
+            closure,
+            when,
+            reported: Arc::default(),
+        }
+    }
+
+    fn render(
+        &self,
+        value: &Value,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        max_rows: usize,
+    ) -> Result<Option<serde_json::Value>, ShellError> {
+        let span = value.span();
+        if let Some(when) = &self.when {
+            let accepted = ClosureEvalOnce::new(engine_state, stack, when.clone())
+                .run_with_value(value.clone())?
+                .into_value(span)?;
+            if !accepted.as_bool()? {
+                return Ok(None);
+            }
+        }
+
+        let rendered = ClosureEvalOnce::new(engine_state, stack, self.closure.clone())
+            .run_with_value(value.clone())?
+            .into_value(span)?;
+        Ok(Some(match rendered {
+            Value::String { val, .. } => serde_json::Value::String(val),
+            Value::Binary { val, .. } => serde_json::Value::String(BASE64.encode(val)),
+            rendered => value_to_json(&rendered, engine_state.signals(), max_rows)?,
+        }))
+    }
+}
@@ -0,0 +1,392 @@
+//! Html tables for lists and records.
+//!
+//! Large tables only show their head and tail, nested lists and records are
+//! collapsible.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use nu_protocol::{Config, Record, Value};
+
+use crate::nu::settings::TableSettings;
+
+/// Rows shown if neither the settings nor the config set a limit.
+const DEFAULT_MAX_ROWS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableLimits {
+    pub max_rows: usize,
+    pub max_columns: usize,
+    pub max_depth: usize,
+}
+
+impl TableLimits {
+    /// Limits of the `nuju settings`, rows fall back to the
+    /// `abbreviated_row_count` of `$env.config.table`.
+    pub fn new(settings: &TableSettings, config: &Config) -> Self {
+        let max_rows = settings
+            .max_rows
+            .or(config
+                .table
+                .abbreviated_row_count
+                .map(|count| count.saturating_mul(2)))
+            .unwrap_or(DEFAULT_MAX_ROWS);
+        Self {
+            max_rows,
+            max_columns: settings.max_columns,
+            max_depth: settings.max_depth,
+        }
+    }
+}
+
+/// Render lists and records as html table.
+///
+/// Returns `None` for other values.
+pub fn render(value: &Value, limits: TableLimits, config: &Config) -> Option<String> {
+    if !matches!(value, Value::List { .. } | Value::Record { .. }) {
+        return None;
+    }
+
+    let mut table = Table {
+        limits,
+        config,
+        depth: 0,
+        html: String::new(),
+    };
+    table.value(value);
+    Some(table.html)
+}
+
+/// Cut lists, records and the columns of tables down to the rows and columns
+/// shown, with a string marking the elided ones.
+///
+/// Returns `None` if the value is within the limits.
+pub fn elide(value: &Value, limits: TableLimits) -> Option<Value> {
+    let span = value.span();
+    match value {
+        Value::Record { val, .. } => {
+            let (head, tail) = visible(val.len(), limits.max_rows);
+            if tail.start == head.end {
+                return None;
+            }
+            let mut record = elide_record(val, head.clone(), tail.clone());
+            record.insert("…", Value::string(more(tail.start - head.end), span));
+            Some(Value::record(record, span))
+        }
+        Value::List { vals, .. } => {
+            let (head, tail) = visible(vals.len(), limits.max_rows);
+            let columns = match is_table(vals) {
+                true => nu_engine::column::get_columns(vals).len(),
+                false => 0,
+            };
+            let (column_head, column_tail) = visible(columns, limits.max_columns);
+            let rows_elided = tail.start > head.end;
+            let columns_elided = column_tail.start > column_head.end;
+            if !rows_elided && !columns_elided {
+                return None;
+            }
+
+            let row = |row: &Value| match (row, columns_elided) {
+                (Value::Record { val, .. }, true) => {
+                    let record = elide_record(val, column_head.clone(), column_tail.clone());
+                    Value::record(record, row.span())
+                }
+                (row, _) => row.clone(),
+            };
+            let mut rows: Vec<Value> = vals[head.clone()].iter().map(row).collect();
+            if rows_elided {
+                rows.push(Value::string(more(tail.start - head.end), span));
+            }
+            rows.extend(vals[tail].iter().map(row));
+            Some(Value::list(rows, span))
+        }
+        _ => None,
+    }
+}
+
+fn elide_record(record: &Record, head: Range<usize>, tail: Range<usize>) -> Record {
+    record
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| head.contains(i) || tail.contains(i))
+        .map(|(_, (column, value))| (column.clone(), value.clone()))
+        .collect()
+}
+
+struct Table<'c> {
+    limits: TableLimits,
+    config: &'c Config,
+    /// Nesting depth of the current value.
+    depth: usize,
+    html: String,
+}
+
+impl Table<'_> {
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Record { val, .. } => {
+                let (head, tail) = visible(val.len(), self.limits.max_rows);
+                self.html.push_str("<table><tbody>");
+                for (i, (column, value)) in val.iter().enumerate() {
+                    if i == head.end && tail.start > head.end {
+                        self.elision_row(tail.start - head.end, 2);
+                    }
+                    if head.contains(&i) || tail.contains(&i) {
+                        self.html.push_str("<tr>");
+                        write_header(&mut self.html, column, "left");
+                        self.cell(value);
+                        self.html.push_str("</tr>");
+                    }
+                }
+                self.html.push_str("</tbody></table>");
+            }
+            Value::List { vals, .. } if vals.is_empty() => self.html.push_str("<i>empty list</i>"),
+            Value::List { vals, .. } => match is_table(vals) {
+                true => self.table(vals),
+                false => self.list(vals),
+            },
+            value => self.cell(value),
+        }
+    }
+
+    fn table(&mut self, rows: &[Value]) {
+        let columns = nu_engine::column::get_columns(rows);
+        let (column_head, column_tail) = visible(columns.len(), self.limits.max_columns);
+        let elided = column_tail.start > column_head.end;
+        let columns: Vec<Option<&String>> = columns[column_head.clone()]
+            .iter()
+            .map(Some)
+            .chain(elided.then_some(None))
+            .chain(columns[column_tail].iter().map(Some))
+            .collect();
+
+        self.html.push_str("<table><thead><tr>");
+        write_header(&mut self.html, "#", "right");
+        for column in columns.iter() {
+            write_header(&mut self.html, column.map_or("…", String::as_str), "left");
+        }
+        self.html.push_str("</tr></thead><tbody>");
+
+        self.rows(rows.len(), columns.len() + 1, |table, i| {
+            let Value::Record { val, .. } = &rows[i]
+            else {
+                unreachable!("tables only contain records");
+            };
+            for column in columns.iter() {
+                match column.map(|column| val.get(column)) {
+                    Some(Some(value)) => table.cell(value),
+                    Some(None) => table.html.push_str("<td></td>"),
+                    None => table.html.push_str("<td>…</td>"),
+                }
+            }
+        });
+        self.html.push_str("</tbody></table>");
+    }
+
+    fn list(&mut self, vals: &[Value]) {
+        self.html.push_str("<table><tbody>");
+        self.rows(vals.len(), 2, |table, i| table.cell(&vals[i]));
+        self.html.push_str("</tbody></table>");
+    }
+
+    /// Write the visible rows with their index, `row` writes the cells.
+    fn rows(&mut self, len: usize, width: usize, mut row: impl FnMut(&mut Self, usize)) {
+        let (head, tail) = visible(len, self.limits.max_rows);
+        for i in head.clone() {
+            self.row(i, &mut row);
+        }
+        if tail.start > head.end {
+            self.elision_row(tail.start - head.end, width);
+        }
+        for i in tail {
+            self.row(i, &mut row);
+        }
+    }
+
+    fn row(&mut self, i: usize, row: &mut impl FnMut(&mut Self, usize)) {
+        self.html.push_str("<tr>");
+        write_header(&mut self.html, &i.to_string(), "right");
+        row(self, i);
+        self.html.push_str("</tr>");
+    }
+
+    fn elision_row(&mut self, omitted: usize, width: usize) {
+        let _ = write!(
+            self.html,
+            r#"<tr><td colspan="{width}" style="text-align:center">{}</td></tr>"#,
+            more(omitted)
+        );
+    }
+
+    fn cell(&mut self, value: &Value) {
+        let summary = match value {
+            Value::Record { val, .. } => count(val.len(), "record", "field"),
+            Value::List { vals, .. } if !vals.is_empty() && is_table(vals) => {
+                count(vals.len(), "table", "row")
+            }
+            Value::List { vals, .. } => count(vals.len(), "list", "item"),
+            value => {
+                let text = match value {
+                    Value::Error { error, .. } => error.to_string(),
+                    value => value.to_expanded_string(", ", self.config),
+                };
+                let _ = write!(
+                    self.html,
+                    r#"<td style="text-align:{}">{}</td>"#,
+                    alignment(value),
+                    escape(&text)
+                );
+                return;
+            }
+        };
+
+        // deeply nested data would still produce huge html
+        if self.depth >= self.limits.max_depth {
+            let _ = write!(self.html, r#"<td style="text-align:left">{summary}</td>"#);
+            return;
+        }
+
+        let _ = write!(
+            self.html,
+            r#"<td style="text-align:left"><details><summary>{summary}</summary>"#
+        );
+        self.depth += 1;
+        self.value(value);
+        self.depth -= 1;
+        self.html.push_str("</details></td>");
+    }
+}
+
+/// Visible head and tail indices of `len` items if at most `max` are shown.
+fn visible(len: usize, max: usize) -> (Range<usize>, Range<usize>) {
+    if len <= max {
+        return (0..len, len..len);
+    }
+    let head = max.div_ceil(2);
+    let tail = max / 2;
+    (0..head, len - tail..len)
+}
+
+/// Marker for elided rows.
+fn more(omitted: usize) -> String {
+    match omitted {
+        1 => "… 1 more row …".to_owned(),
+        n => format!("… {n} more rows …"),
+    }
+}
+
+/// Count of things with the right plural, like nushell's `record 1 field`.
+fn count(n: usize, kind: &str, thing: &str) -> String {
+    match n {
+        1 => format!("{kind} 1 {thing}"),
+        n => format!("{kind} {n} {thing}s"),
+    }
+}
+
+/// Whether the list is a table, a list of records.
+fn is_table(vals: &[Value]) -> bool {
+    vals.iter().all(|val| matches!(val, Value::Record { .. }))
+}
+
+fn alignment(value: &Value) -> &'static str {
+    match value {
+        Value::Int { .. } |
+        Value::Float { .. } |
+        Value::Filesize { .. } |
+        Value::Duration { .. } |
+        Value::Date { .. } => "right",
+        Value::Bool { .. } => "center",
+        _ => "left",
+    }
+}
+
+fn write_header(html: &mut String, text: &str, alignment: &str) {
+    let _ = write!(
+        html,
+        r#"<th style="text-align:{alignment}">{}</th>"#,
+        escape(text)
+    );
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use nu_protocol::{Span, record};
+
+    use super::*;
+
+    #[test]
+    fn elide_rows_and_columns() {
+        assert_eq!(visible(3, 5), (0..3, 3..3));
+        assert_eq!(visible(10, 5), (0..3, 8..10));
+
+        let limits = TableLimits {
+            max_rows: 2,
+            max_columns: 2,
+            max_depth: 1,
+        };
+        let span = Span::unknown();
+        let row = |i| {
+            Value::record(
+                record! {
+                    "a" => Value::int(i, span),
+                    "b" => Value::string("<b>", span),
+                    "c" => Value::bool(true, span),
+                },
+                span,
+            )
+        };
+        let table = Value::list((0..5).map(row).collect(), span);
+        let html = render(&table, limits, &Config::default()).unwrap();
+        assert!(html.contains("… 3 more rows …"));
+        assert!(html.contains(r#"<th style="text-align:right">4</th>"#));
+        assert!(!html.contains(r#"<th style="text-align:right">1</th>"#));
+        assert!(html.contains(r#"<td style="text-align:right">0</td>"#));
+        assert!(!html.contains("&lt;b&gt;"));
+        assert!(html.contains(r#"<td style="text-align:center">true</td>"#));
+
+        assert_eq!(
+            render(&Value::int(1, span), limits, &Config::default()),
+            None
+        );
+
+        let Some(Value::List { vals, .. }) = elide(&table, limits)
+        else {
+            panic!("table exceeds the limits");
+        };
+        assert_eq!(vals.len(), 3);
+        assert_eq!(vals[1].as_str().unwrap(), "… 3 more rows …");
+        assert_eq!(vals[2].as_record().unwrap().columns().count(), 2);
+        assert_eq!(elide(&Value::list(vec![], span), limits), None);
+    }
+
+    #[test]
+    fn limit_nesting_depth() {
+        let limits = TableLimits {
+            max_rows: 10,
+            max_columns: 10,
+            max_depth: 1,
+        };
+        let span = Span::unknown();
+        let inner = Value::list(vec![Value::string("deep", span)], span);
+        let nested = Value::list(vec![Value::list(vec![inner], span)], span);
+        let html = render(&nested, limits, &Config::default()).unwrap();
+        assert!(html.contains("<summary>list 1 item</summary>"));
+        assert!(html.contains(r#"<td style="text-align:left">list 1 item</td>"#));
+        assert!(!html.contains("deep"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Kernel settings changed via `nuju settings`.
+
+use std::sync::Arc;
+
+use nu_protocol::{IntoValue, Record, ShellError, Span, Value, record};
+use parking_lot::Mutex;
+
+pub type SharedSettings = Arc<Mutex<Settings>>;
+
+#[derive(Debug, Clone, Default, IntoValue)]
+pub struct Settings {
+    pub history: HistorySettings,
+    pub table: TableSettings,
+}
+
+#[derive(Debug, Clone, Default, IntoValue)]
+pub struct HistorySettings {
+    /// Store the plain text output of cells in the history file.
+    pub output: bool,
+}
+
+/// Limits of rendered lists and records, larger ones only show their head and
+/// tail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableSettings {
+    /// Rows shown of lists and records, split between head and tail.
+    ///
+    /// Without a value, twice the `abbreviated_row_count` of
+    /// `$env.config.table` is used like `table` does.
+    pub max_rows: Option<usize>,
+    /// Columns shown of tables, split between head and tail.
+    pub max_columns: usize,
+    /// Nesting depth of lists and records shown in html tables.
+    pub max_depth: usize,
+}
+
+impl Default for TableSettings {
+    fn default() -> Self {
+        Self {
+            max_rows: None,
+            max_columns: 20,
+            max_depth: 3,
+        }
+    }
+}
+
+impl IntoValue for TableSettings {
+    fn into_value(self, span: Span) -> Value {
+        let limit = |limit: usize| Value::int(limit as i64, span);
+        Value::record(
+            record! {
+                "max_rows" => self.max_rows.map_or(Value::nothing(span), limit),
+                "max_columns" => limit(self.max_columns),
+                "max_depth" => limit(self.max_depth),
+            },
+            span,
+        )
+    }
+}
+
+impl Settings {
+    /// Apply the settings of a record, e.g. `{history: {output: true}}`.
+    ///
+    /// Settings missing in the record are kept, on errors nothing is applied.
+    pub fn update(&mut self, value: &Value, call_span: Span) -> Result<(), ShellError> {
+        let mut settings = self.clone();
+        for (section, value) in fields(value, "settings", call_span)? {
+            if !matches!(section.as_str(), "history" | "table") {
+                return Err(unknown_setting(section, value, call_span));
+            }
+            for (key, value) in fields(value, section, call_span)? {
+                match (section.as_str(), key.as_str()) {
+                    ("history", "output") => settings.history.output = value.as_bool()?,
+                    ("table", "max_rows") => {
+                        settings.table.max_rows = Some(limit(value, call_span)?)
+                    }
+                    ("table", "max_columns") => {
+                        settings.table.max_columns = limit(value, call_span)?
+                    }
+                    ("table", "max_depth") => settings.table.max_depth = limit(value, call_span)?,
+                    _ => {
+                        let name = format!("{section}.{key}");
+                        return Err(unknown_setting(&name, value, call_span));
+                    }
+                }
+            }
+        }
+        *self = settings;
+        Ok(())
+    }
+}
+
+fn fields<'v>(value: &'v Value, name: &str, call_span: Span) -> Result<&'v Record, ShellError> {
+    value.as_record().map_err(|_| ShellError::IncorrectValue {
+        msg: format!("{name} must be a record"),
+        val_span: value.span(),
+        call_span,
+    })
+}
+
+fn limit(value: &Value, call_span: Span) -> Result<usize, ShellError> {
+    usize::try_from(value.as_int()?).map_err(|_| ShellError::IncorrectValue {
+        msg: "limits must not be negative".to_owned(),
+        val_span: value.span(),
+        call_span,
+    })
+}
+
+fn unknown_setting(name: &str, value: &Value, call_span: Span) -> ShellError {
+    ShellError::IncorrectValue {
+        msg: format!("unknown setting `{name}`"),
+        val_span: value.span(),
+        call_span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_settings() {
+        let span = Span::unknown();
+        let mut settings = Settings::default();
+        let update = Value::test_record(record! {
+            "table" => Value::test_record(record! {
+                "max_rows" => Value::test_int(10),
+                "max_depth" => Value::test_int(1),
+            }),
+        });
+        settings.update(&update, span).unwrap();
+        assert_eq!(settings.table.max_rows, Some(10));
+        assert_eq!(settings.table.max_columns, 20);
+        assert_eq!(settings.table.max_depth, 1);
+
+        let invalid = Value::test_record(record! {
+            "history" => Value::test_record(record! {"output" => Value::test_bool(true)}),
+            "table" => Value::test_record(record! {"max_rows" => Value::test_int(-1)}),
+        });
+        assert!(settings.update(&invalid, span).is_err());
+        assert!(!settings.history.output);
+
+        let unknown = Value::test_record(record! {
+            "table" => Value::test_record(record! {"rows" => Value::test_int(1)}),
+        });
+        assert!(settings.update(&unknown, span).is_err());
+    }
+}
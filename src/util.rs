@@ -3,3 +3,24 @@ pub enum Select<L, R> {
     Left(L),
     Right(R),
 }
+
+/// Convert a cursor position in unicode code points into a byte offset.
+///
+/// Jupyter counts cursor positions in code points while nu works on bytes.
+/// Positions past the end are clamped to the length of `s`.
+pub fn char_to_byte_offset(s: &str, char_offset: usize) -> usize {
+    s.char_indices()
+        .nth(char_offset)
+        .map(|(byte_offset, _)| byte_offset)
+        .unwrap_or(s.len())
+}
+
+/// Convert a byte offset into a cursor position in unicode code points.
+///
+/// See [`char_to_byte_offset`] for the other direction.
+pub fn byte_to_char_offset(s: &str, byte_offset: usize) -> usize {
+    let byte_offset = byte_offset.min(s.len());
+    s.char_indices()
+        .take_while(|(i, _)| *i < byte_offset)
+        .count()
+}
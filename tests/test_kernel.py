@@ -1,5 +1,5 @@
-import json
-
+import os
+import time
 import pytest
 import tomllib
 from jupyter_client import BlockingKernelClient, KernelManager
@@ -9,9 +9,11 @@ TIMEOUT = 10
 
 
 @pytest.fixture
-def kernel():
+def kernel(tmp_path):
     km = KernelManager(kernel_name="nu")
-    km.start_kernel()
+    # keep the history of the user clean
+    history = str(tmp_path / "history.jsonl")
+    km.start_kernel(env=os.environ | {"NU_JUPYTER_KERNEL_HISTORY": history})
     yield km.client()
     km.shutdown_kernel()
 
@@ -25,6 +27,11 @@ def ok(client: BlockingKernelClient, code: str) -> list[dict]:
     busy_status = client.get_iopub_msg(timeout=TIMEOUT)
     assert busy_status["content"]["execution_state"] == "busy"
 
+    # followed by the code that is about to run
+    execute_input = client.get_iopub_msg(timeout=TIMEOUT)
+    assert execute_input["msg_type"] == "execute_input"
+    assert execute_input["content"]["code"] == code
+
     # check on the iopub channel until we receive an idle message
     contents = []
     while True:
@@ -78,7 +85,7 @@ def test_nuju_content(kernel: BlockingKernelClient):
     contents = ok(kernel, "$nuju")
     assert len(contents) == 1
     data = contents[0]["data"]
-    nuju_constant = json.loads(data["application/json"])
+    nuju_constant = data["application/json"]
     with open("Cargo.toml", "rb") as cargo_toml_file:
         cargo_toml = tomllib.load(cargo_toml_file)
         assert nuju_constant["version"]["kernel"] == cargo_toml["package"]["version"]
@@ -95,3 +102,458 @@ def test_persistence(kernel: BlockingKernelClient):
     assert len(get_value) == 1
 
     assert get_value[0]["data"]["text/plain"] == "bar"
+
+
+def test_complete(kernel: BlockingKernelClient):
+    ok(kernel, "let foo = {alpha: 1, beta: 2}")
+
+    kernel.complete("$foo.al", 7)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    assert reply["status"] == "ok"
+    assert reply["matches"] == ["alpha"]
+    assert reply["cursor_start"] == 5
+    assert reply["cursor_end"] == 7
+
+
+def test_inspect(kernel: BlockingKernelClient):
+    ok(kernel, "let foo = {alpha: 1, beta: 2}")
+
+    kernel.inspect("$foo", 2)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    assert reply["status"] == "ok"
+    assert reply["found"]
+    assert reply["data"]["text/plain"].startswith("$foo: record<alpha: int, beta: int>")
+    assert "text/markdown" in reply["data"]
+
+    kernel.inspect("nuju", 4)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    assert reply["found"]
+    assert "nuju display" in reply["data"]["text/plain"]
+
+
+def test_is_complete(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+
+    kernel.is_complete("ls | where size > 1kb")
+    assert kernel.get_shell_msg(timeout=TIMEOUT)["content"]["status"] == "complete"
+
+    kernel.is_complete("ls | each {|file|")
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    assert reply["status"] == "incomplete"
+    assert reply["indent"] == "    "
+
+
+def test_input(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    kernel.execute("input 'Name: '", allow_stdin=True)
+
+    input_request = kernel.get_stdin_msg(timeout=TIMEOUT)["content"]
+    assert input_request["prompt"] == "Name: "
+    assert not input_request["password"]
+    kernel.input("nu")
+
+    shell_reply = kernel.get_shell_msg(timeout=TIMEOUT)
+    assert shell_reply["content"]["status"] == "ok"
+
+
+def test_comm(kernel: BlockingKernelClient):
+    contents = ok(kernel, "nuju comm open test-target {answer: 42}")
+    comm_open = next(c for c in contents if "target_name" in c)
+    assert comm_open["target_name"] == "test-target"
+    assert comm_open["data"] == {"answer": 42}
+
+    kernel.comm_info("test-target")
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    assert reply["status"] == "ok"
+    assert reply["comms"] == {comm_open["comm_id"]: {"target_name": "test-target"}}
+
+    # comm messages have no reply, so failing handlers are reported on iopub
+    ok(kernel, "nuju comm on-msg test-target {|msg| error make {msg: 'broken handler'}}")
+    kernel.shell_channel.send(
+        kernel.session.msg("comm_msg", {"comm_id": comm_open["comm_id"], "data": {}})
+    )
+    assert kernel.get_iopub_msg(timeout=TIMEOUT)["content"]["execution_state"] == "busy"
+    error = kernel.get_iopub_msg(timeout=TIMEOUT)
+    assert error["msg_type"] == "error"
+    assert "broken handler" in error["content"]["evalue"]
+    assert kernel.get_iopub_msg(timeout=TIMEOUT)["content"]["execution_state"] == "idle"
+
+    # comms opened by the frontend pass their data to the handler, which doesn't
+    # inherit the flags of the last cell
+    ok(kernel, "nuju comm on-msg echo-target {|msg| nuju print $msg.data.text}")
+    kernel.execute_interactive("1", silent=True, timeout=TIMEOUT)
+    content = {"comm_id": "frontend-comm", "target_name": "echo-target", "data": {"text": "hi"}}
+    kernel.shell_channel.send(kernel.session.msg("comm_open", content))
+    display = kernel.get_iopub_msg(timeout=TIMEOUT)
+    while display["msg_type"] != "display_data":
+        display = kernel.get_iopub_msg(timeout=TIMEOUT)
+    assert display["content"]["data"]["text/plain"] == "hi"
+
+
+def test_silent(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    count = kernel.execute_interactive("1", timeout=TIMEOUT)["content"]["execution_count"]
+
+    def no_output(msg: dict):
+        assert msg["msg_type"] == "status", f"unexpected output: {msg}"
+
+    reply = kernel.execute_interactive(
+        "nuju print 'hidden'; 2", silent=True, output_hook=no_output, timeout=TIMEOUT
+    )
+    assert reply["content"]["status"] == "ok"
+    assert reply["content"]["execution_count"] == count + 1
+
+
+def test_user_expressions(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    reply = kernel.execute_interactive(
+        "let foo = 21",
+        user_expressions={
+            "double": "$foo * 2",
+            "missing": "$bar",
+            "leak": "$env.LEAKED = 1; def leaked [] {}",
+        },
+        timeout=TIMEOUT,
+    )
+    user_expressions = reply["content"]["user_expressions"]
+    assert user_expressions["double"]["status"] == "ok"
+    assert user_expressions["double"]["data"]["text/plain"] == "42"
+    assert user_expressions["missing"]["status"] == "error"
+
+    # user expressions don't change the session
+    contents = ok(kernel, "'LEAKED' in $env or (scope commands | any {$in.name == leaked})")
+    assert contents[0]["data"]["text/plain"] == "false"
+
+
+def test_stop_on_error(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    failing = kernel.execute("error make {msg: 'boom'}", stop_on_error=True)
+    queued = kernel.execute("1")
+
+    replies = {}
+    while len(replies) < 2:
+        reply = kernel.get_shell_msg(timeout=TIMEOUT)
+        replies[reply["parent_header"]["msg_id"]] = reply["content"]
+    assert replies[failing]["status"] == "error"
+    assert replies[queued]["status"] == "aborted"
+
+    # requests sent a while after the failed reply are executed again
+    time.sleep(0.5)
+    reply = kernel.execute("1", reply=True, timeout=TIMEOUT)
+    assert reply["content"]["status"] == "ok"
+
+
+def test_error(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    errors = []
+    reply = kernel.execute_interactive(
+        "error make {msg: 'boom'}",
+        output_hook=lambda msg: errors.append(msg["content"]) if msg["msg_type"] == "error" else None,
+        timeout=TIMEOUT,
+    )
+    assert reply["content"]["status"] == "error"
+    assert len(errors) == 1
+    assert errors[0]["evalue"] == "boom"
+    assert any("boom" in line for line in errors[0]["traceback"])
+
+
+def test_update_display(kernel: BlockingKernelClient):
+    contents = ok(kernel, "nuju print --display-id counter 1; nuju update counter 2")
+    assert len(contents) == 2
+    assert contents[0]["transient"] == {"display_id": "counter"}
+    assert contents[0]["data"]["text/plain"] == "1"
+    assert contents[1]["transient"] == {"display_id": "counter"}
+    assert contents[1]["data"]["text/plain"] == "2"
+
+    # silent prints display nothing, so there is nothing to update
+    kernel.execute("nuju print --display-id hidden 1", silent=True, reply=True, timeout=TIMEOUT)
+    reply = kernel.execute_interactive("nuju update hidden 2", timeout=TIMEOUT)
+    assert reply["content"]["status"] == "error"
+
+
+def test_clear(kernel: BlockingKernelClient):
+    contents = ok(kernel, "nuju print 1; nuju clear --wait")
+    assert len(contents) == 2
+    assert contents[1] == {"wait": True}
+
+
+def test_history(kernel: BlockingKernelClient):
+    ok(kernel, "{history: {output: true}} | nuju settings")
+    ok(kernel, "let history_marker = 'first'")
+    ok(kernel, "$history_marker")
+
+    kernel.history(hist_access_type="tail", n=2, output=True)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    assert reply["status"] == "ok"
+    assert [entry[2] for entry in reply["history"]] == [
+        ["let history_marker = 'first'", None],
+        ["$history_marker", "first"],
+    ]
+
+    kernel.history(hist_access_type="search", pattern="*history_marker*", unique=True)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    assert [entry[2] for entry in reply["history"]] == [
+        "let history_marker = 'first'",
+        "$history_marker",
+    ]
+
+    # failed cells don't take the line of the next cell
+    failed = kernel.execute_interactive("error make {msg: 'history failure'}", timeout=TIMEOUT)
+    assert failed["content"]["status"] == "error"
+    kernel.execute_interactive("'after failure'", timeout=TIMEOUT)
+    kernel.history(hist_access_type="range", session=0, start=0, output=False)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)["content"]
+    lines = [entry[1] for entry in reply["history"]]
+    assert len(lines) == len(set(lines))
+    assert reply["history"][-1][2] == "'after failure'"
+
+
+def test_unknown_request(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    msg = kernel.session.msg("foo_request", {})
+    kernel.shell_channel.send(msg)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)
+    assert reply["msg_type"] == "foo_reply"
+    assert reply["content"]["status"] == "error"
+    assert reply["content"]["ename"] == "UnknownRequest"
+    assert kernel.get_iopub_msg(timeout=TIMEOUT)["content"]["execution_state"] == "busy"
+    assert kernel.get_iopub_msg(timeout=TIMEOUT)["content"]["execution_state"] == "idle"
+
+    # the kernel keeps working after a bad request
+    contents = ok(kernel, "1 + 1")
+    assert contents[0]["data"]["text/plain"] == "2"
+
+
+def test_debug(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+
+    def debug(seq: int, command: str, arguments: dict) -> dict:
+        content = {"seq": seq, "type": "request", "command": command, "arguments": arguments}
+        kernel.control_channel.send(kernel.session.msg("debug_request", content))
+        reply = kernel.control_channel.get_msg(timeout=TIMEOUT)
+        assert reply["msg_type"] == "debug_reply"
+        assert reply["content"]["success"]
+        return reply["content"]["body"]
+
+    debug(1, "initialize", {})
+    debug(2, "attach", {})
+    info = debug(3, "debugInfo", {})
+    assert info["isStarted"]
+    assert info["hashMethod"] == "Murmur2"
+
+    path = debug(4, "dumpCell", {"code": "let x = 1"})["sourcePath"]
+    assert path.startswith(info["tmpFilePrefix"])
+    assert path.endswith(info["tmpFileSuffix"])
+    assert debug(5, "source", {"source": {"path": path}})["content"] == "let x = 1"
+
+    # a breakpoint pauses the cell until it is continued
+    code = "let x = 1\nlet y = $x + 1\n$y"
+    path = debug(6, "dumpCell", {"code": code})["sourcePath"]
+    debug(7, "setBreakpoints", {"source": {"path": path}, "breakpoints": [{"line": 2}]})
+    debug(8, "configurationDone", {})
+    kernel.execute(code)
+    while True:
+        msg = kernel.get_iopub_msg(timeout=TIMEOUT)
+        if msg["msg_type"] == "debug_event" and msg["content"]["event"] == "stopped":
+            break
+    assert msg["content"]["body"]["reason"] == "breakpoint"
+
+    frames = debug(9, "stackTrace", {"threadId": 1})["stackFrames"]
+    assert frames[0]["line"] == 2
+    assert frames[0]["source"]["path"] == path
+    locals = debug(10, "scopes", {"frameId": 0})["scopes"][0]
+    variables = debug(11, "variables", {"variablesReference": locals["variablesReference"]})
+    assert [(v["name"], v["value"]) for v in variables["variables"]] == [("x", "1")]
+
+    debug(12, "continue", {"threadId": 1})
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)
+    assert reply["content"]["status"] == "ok"
+
+
+def test_subshells(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    ok(kernel, "let answer = 42")
+
+    def control(msg_type: str, content: dict) -> dict:
+        kernel.control_channel.send(kernel.session.msg(msg_type, content))
+        reply = kernel.control_channel.get_msg(timeout=TIMEOUT)
+        assert reply["content"]["status"] == "ok"
+        return reply["content"]
+
+    subshell_id = control("create_subshell_request", {})["subshell_id"]
+    assert control("list_subshell_request", {})["subshell_id"] == [subshell_id]
+
+    # the subshell answers while the main shell is busy
+    kernel.execute("sleep 2sec")
+    msg = kernel.session.msg("execute_request", {"code": "$answer", "silent": False})
+    msg["header"]["subshell_id"] = subshell_id
+    kernel.shell_channel.send(msg)
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)
+    assert reply["parent_header"]["msg_id"] == msg["header"]["msg_id"]
+    assert reply["content"]["status"] == "ok"
+    kernel.get_shell_msg(timeout=TIMEOUT)
+
+    # comm messages arrive at the main shell, but run the handler in its shell
+    kernel.execute("nuju comm open sub-target")
+    assert kernel.get_shell_msg(timeout=TIMEOUT)["content"]["status"] == "ok"
+    comm_open = kernel.get_iopub_msg(timeout=TIMEOUT)
+    while comm_open["msg_type"] != "comm_open":
+        comm_open = kernel.get_iopub_msg(timeout=TIMEOUT)
+    comm_id = comm_open["content"]["comm_id"]
+    code = "nuju comm on-msg sub-target {|msg| nuju comm send $msg.comm_id $msg.data}"
+    msg = kernel.session.msg("execute_request", {"code": code, "silent": False})
+    msg["header"]["subshell_id"] = subshell_id
+    kernel.shell_channel.send(msg)
+    assert kernel.get_shell_msg(timeout=TIMEOUT)["content"]["status"] == "ok"
+    kernel.shell_channel.send(
+        kernel.session.msg("comm_msg", {"comm_id": comm_id, "data": {"echo": 1}})
+    )
+    echo = kernel.get_iopub_msg(timeout=TIMEOUT)
+    while echo["msg_type"] != "comm_msg":
+        echo = kernel.get_iopub_msg(timeout=TIMEOUT)
+    assert echo["content"]["data"] == {"echo": 1}
+
+    control("delete_subshell_request", {"subshell_id": subshell_id})
+    assert control("list_subshell_request", {})["subshell_id"] == []
+
+
+def test_usage(kernel: BlockingKernelClient):
+    kernel.wait_for_ready(timeout=TIMEOUT)
+    kernel.control_channel.send(kernel.session.msg("usage_request", {}))
+    reply = kernel.control_channel.get_msg(timeout=TIMEOUT)
+    assert reply["msg_type"] == "usage_reply"
+    assert reply["content"]["kernel_memory"] > 0
+    assert reply["content"]["cpu_count"] > 0
+
+    contents = ok(kernel, "(nuju usage).kernel_memory > 0B")
+    assert contents[0]["data"]["text/plain"] == "true"
+
+
+def test_streaming(kernel: BlockingKernelClient):
+    contents = ok(kernel, "1..5 | each {|i| sleep 100ms; $i}")
+    display_ids = {
+        content["transient"]["display_id"] for content in contents if "transient" in content
+    }
+    assert len(display_ids) == 1
+    # the display ends up showing the full list instead of a separate result
+    assert contents[-1]["transient"]["display_id"] in display_ids
+    assert contents[-1]["data"]["text/plain"] == "1\n2\n3\n4\n5\n"
+    assert not any("execution_count" in content for content in contents)
+
+    ok(kernel, "nuju external")
+    contents = ok(kernel, "^sh -c 'echo a; sleep 1; echo b'")
+    assert [content["text"] for content in contents] == ["a\n", "b\n"]
+
+    # interrupted externals don't keep running in the background
+    kernel.execute("^sleep 1000")
+    assert kernel.get_iopub_msg(timeout=TIMEOUT)["content"]["execution_state"] == "busy"
+    kernel.control_channel.send(kernel.session.msg("interrupt_request", {}))
+    assert kernel.control_channel.get_msg(timeout=TIMEOUT)["msg_type"] == "interrupt_reply"
+    assert kernel.get_shell_msg(timeout=TIMEOUT)["content"]["status"] == "error"
+    while kernel.get_iopub_msg(timeout=TIMEOUT)["content"].get("execution_state") != "idle":
+        pass
+    contents = ok(kernel, "(nuju usage).children | where name == sleep | is-empty")
+    assert contents[0]["data"]["text/plain"] == "true"
+
+
+def test_binary_rendering(kernel: BlockingKernelClient):
+    contents = ok(kernel, "[1 2 3] | series line | chart 2d | draw png")
+    assert contents[0]["data"]["image/png"].startswith("iVBORw0KGgo")
+    assert contents[0]["metadata"]["image/png"] == {"width": 600, "height": 400}
+
+    # guessed from the magic bytes
+    contents = ok(kernel, "0x[47 49 46 38 39 61 20 00 10 00]")
+    assert contents[0]["metadata"]["image/gif"] == {"width": 32, "height": 16}
+
+
+def test_json_rendering(kernel: BlockingKernelClient):
+    contents = ok(kernel, "{a: [1 2]}")
+    assert contents[0]["data"]["application/json"] == {"a": [1, 2]}
+
+    mime = "application/vnd.vegalite.v5+json"
+    contents = ok(kernel, f"{{mark: bar}} | metadata set --content-type '{mime}'")
+    assert contents[0]["data"][mime] == {"mark": "bar"}
+
+
+def test_render_register(kernel: BlockingKernelClient):
+    ok(kernel, "nuju render register text/latex {|v| $'$($v)$'} --when {|v| $v == 2}")
+    contents = ok(kernel, "2")
+    assert contents[0]["data"]["text/latex"] == "$2$"
+    contents = ok(kernel, "3")
+    assert "text/latex" not in contents[0]["data"]
+
+    # registered renderers override the built-in ones
+    ok(kernel, "nuju render register text/html {|v| '<b>custom</b>'}")
+    contents = ok(kernel, "3")
+    assert contents[0]["data"]["text/html"] == "<b>custom</b>"
+
+    ok(kernel, "nuju render unregister text/html")
+    contents = ok(kernel, "3")
+    assert contents[0]["data"]["text/html"] != "<b>custom</b>"
+
+    # subshells only use the renderers of the main shell from their creation
+    kernel.control_channel.send(kernel.session.msg("create_subshell_request", {}))
+    subshell_id = kernel.control_channel.get_msg(timeout=TIMEOUT)["content"]["subshell_id"]
+    ok(kernel, "nuju render register application/x-main {|v| 'main'}")
+    for code, latex in [("2", True), ("3", False)]:
+        msg = kernel.session.msg("execute_request", {"code": code, "silent": False})
+        msg["header"]["subshell_id"] = subshell_id
+        kernel.shell_channel.send(msg)
+        assert kernel.get_shell_msg(timeout=TIMEOUT)["content"]["status"] == "ok"
+        result = kernel.get_iopub_msg(timeout=TIMEOUT)
+        while result["msg_type"] != "execute_result":
+            result = kernel.get_iopub_msg(timeout=TIMEOUT)
+        assert ("text/latex" in result["content"]["data"]) == latex
+        assert "application/x-main" not in result["content"]["data"]
+
+
+def test_table_rendering(kernel: BlockingKernelClient):
+    ok(kernel, "{table: {max_rows: 4, max_columns: 2, max_depth: 1}} | nuju settings")
+    contents = ok(kernel, "1..1000 | each {|i| {a: $i, b: 1kB, c: {d: [1 2]}}}")
+    data = contents[-1]["data"]
+    html = data["text/html"]
+    assert "… 996 more rows …" in html
+    assert ">999</th>" in html
+    assert ">500</th>" not in html
+    assert '<th style="text-align:left">…</th>' in html
+    assert "kB" not in html
+    assert "<details><summary>record 1 field</summary>" in html
+    assert '<td style="text-align:left">list 2 items</td>' in html
+
+    # other formats are limited too or left out
+    assert "… 996 more rows …" in data["text/plain"]
+    assert "500" not in data["text/plain"]
+    assert "application/json" not in data
+    assert "text/csv" not in data
+
+    contents = ok(kernel, "{size: 1kB, name: nu}")
+    html = contents[-1]["data"]["text/html"]
+    assert '<td style="text-align:right">1' in html
+    assert '<td style="text-align:left">nu</td>' in html
+
+
+def test_restart(kernel: BlockingKernelClient):
+    ok(kernel, "nuju external; let restart_marker = 1; nuju print --display-id restart 1")
+
+    # restart in place, without the kernel manager starting a new process
+    kernel.control_channel.send(kernel.session.msg("shutdown_request", {"restart": True}))
+    reply = kernel.control_channel.get_msg(timeout=TIMEOUT)
+    assert reply["content"] == {"status": "ok", "restart": True}
+
+    kernel.execute("$restart_marker")
+    reply = kernel.get_shell_msg(timeout=TIMEOUT)
+    assert reply["content"]["status"] == "error"
+    assert reply["content"]["execution_count"] == 1
+    while kernel.get_iopub_msg(timeout=TIMEOUT)["content"].get("execution_state") != "idle":
+        pass
+
+    contents = ok(kernel, "1 + 1")
+    assert contents[0]["execution_count"] == 1
+
+    # externals have to be enabled again and displays of the last session are gone
+    for code in ["^echo restarted", "nuju update restart 2"]:
+        kernel.execute(code)
+        reply = kernel.get_shell_msg(timeout=TIMEOUT)
+        assert reply["content"]["status"] == "error"
+        while kernel.get_iopub_msg(timeout=TIMEOUT)["content"].get("execution_state") != "idle":
+            pass